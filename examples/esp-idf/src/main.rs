@@ -1,7 +1,7 @@
 use esp_idf_hal::delay;
 use esp_idf_hal::gpio::PinDriver;
 use esp_idf_hal::prelude::*;
-use uln2003::{StepperMotor, ULN2003};
+use uln2003::{BlockingStepperMotor, StepperMotor, ULN2003};
 
 fn main() {
     // It is necessary to call this function once. Otherwise some patches to the runtime
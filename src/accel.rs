@@ -0,0 +1,273 @@
+use embedded_hal::digital::{InputPin, OutputPin};
+
+use crate::{Direction, MaybeDelay, NoPin, PinGroup, StepError, ULN2003};
+
+/// Wrapper with the API of the Arduino AccelStepper library, to make porting easier.
+/// Moves are non-blocking and accelerate and decelerate with the configured acceleration,
+/// [`run`](Self::run) has to be called as often as possible.
+///
+/// Positions are in half steps like [`ULN2003::position`], speeds and accelerations in steps
+/// of the active drive mode per second. In half step mode (the default) both are the same.
+pub struct AccelStepper<
+    P1,
+    P2,
+    P3,
+    P4,
+    D,
+    E = NoPin,
+    G = NoPin,
+    M = NoPin,
+    F = NoPin,
+    C = fn(i64),
+    S = fn(i64),
+> where
+    P1: OutputPin,
+    P2: OutputPin,
+    P3: OutputPin,
+    P4: OutputPin,
+    D: MaybeDelay,
+    E: OutputPin,
+    G: PinGroup,
+    M: OutputPin,
+    F: InputPin,
+    C: FnMut(i64),
+    S: FnMut(i64),
+{
+    motor: ULN2003<P1, P2, P3, P4, D, E, G, M, F, C, S>,
+    target: i64,
+    speed: f32,
+    max_speed: f32,
+    acceleration: f32,
+    // the acceleration math of AccelStepper: step count into the ramp (negative while
+    // decelerating), current, initial and minimal step interval in us
+    n: i64,
+    cn: f32,
+    c0: f32,
+    cmin: f32,
+    interval_us: u32,
+    last_us: Option<u64>,
+    dir: Direction,
+}
+
+impl<P1, P2, P3, P4, D, E, G, M, F, C, S> AccelStepper<P1, P2, P3, P4, D, E, G, M, F, C, S>
+where
+    P1: OutputPin,
+    P2: OutputPin,
+    P3: OutputPin,
+    P4: OutputPin,
+    D: MaybeDelay,
+    E: OutputPin,
+    G: PinGroup,
+    M: OutputPin,
+    F: InputPin,
+    C: FnMut(i64),
+    S: FnMut(i64),
+{
+    /// Wrap `motor`, with a maximum speed and acceleration of 1 like AccelStepper
+    pub fn new(motor: ULN2003<P1, P2, P3, P4, D, E, G, M, F, C, S>) -> Self {
+        let mut stepper = Self {
+            target: motor.position,
+            motor,
+            speed: 0.0,
+            max_speed: 0.0,
+            acceleration: 0.0,
+            n: 0,
+            cn: 0.0,
+            c0: 0.0,
+            cmin: 1.0,
+            interval_us: 0,
+            last_us: None,
+            dir: Direction::Normal,
+        };
+        stepper.set_max_speed(1.0);
+        stepper.set_acceleration(1.0);
+        stepper
+    }
+
+    /// Set the absolute target position. The motor accelerates towards it with the next calls
+    /// to [`run`](Self::run), the target can be changed at any time.
+    pub fn move_to(&mut self, absolute: i64) {
+        let target = self.motor.limit(self.motor.resolve(absolute));
+        if target != self.target {
+            self.target = target;
+            self.compute_new_speed();
+        }
+    }
+
+    /// Set the target relative to the current position
+    pub fn move_rel(&mut self, relative: i64) {
+        self.move_to(self.motor.position().saturating_add(relative));
+    }
+
+    /// Set the maximum speed in steps per second
+    pub fn set_max_speed(&mut self, speed: f32) {
+        let speed = speed.abs();
+        if speed > 0.0 && speed != self.max_speed {
+            self.max_speed = speed;
+            self.cmin = 1_000_000.0 / speed;
+            if self.n > 0 {
+                self.n = self.steps_to_stop();
+                self.compute_new_speed();
+            }
+        }
+    }
+
+    /// Maximum speed in steps per second
+    pub fn max_speed(&self) -> f32 {
+        self.max_speed
+    }
+
+    /// Set the acceleration and deceleration in steps per second squared
+    pub fn set_acceleration(&mut self, acceleration: f32) {
+        let acceleration = acceleration.abs();
+        if acceleration > 0.0 && acceleration != self.acceleration {
+            if self.acceleration > 0.0 {
+                self.n = (self.n as f32 * (self.acceleration / acceleration)) as i64;
+            }
+            // the first interval of the ramp, 0.676 corrects the error of the recursion
+            self.c0 = 0.676 * sqrt(2.0 / acceleration) * 1_000_000.0;
+            self.acceleration = acceleration;
+            self.compute_new_speed();
+        }
+    }
+
+    /// Do a step if one is due, accelerating or decelerating as needed. `now_us` is the current
+    /// time in microseconds from any monotonic clock. Returns whether the motor is still
+    /// moving towards the target.
+    pub fn run(&mut self, now_us: u64) -> Result<bool, StepError> {
+        if self.run_speed(now_us)? {
+            self.compute_new_speed();
+            if self.interval_us == 0 {
+                // stopped at the target, apply the idle behavior like other moves
+                self.motor.finish_move(true)?;
+            }
+        }
+        Ok(self.speed != 0.0 || self.distance_to_go() != 0)
+    }
+
+    /// Decelerate to a stop as fast as the acceleration allows, by moving the target
+    pub fn stop(&mut self) {
+        if self.speed != 0.0 {
+            let steps = (self.steps_to_stop() + 1) * self.motor.drive_mode.half_steps() as i64;
+            self.move_rel(if self.speed > 0.0 { steps } else { -steps });
+        }
+    }
+
+    /// Half steps left until the target is reached, negative in reverse direction
+    pub fn distance_to_go(&self) -> i64 {
+        self.target.saturating_sub(self.motor.position)
+    }
+
+    /// The absolute target position
+    pub fn target_position(&self) -> i64 {
+        self.target
+    }
+
+    /// The current position, same as [`ULN2003::position`]
+    pub fn current_position(&self) -> i64 {
+        self.motor.position()
+    }
+
+    /// Current speed in steps per second, negative in reverse direction
+    pub fn speed(&self) -> f32 {
+        self.speed
+    }
+
+    /// Whether the motor is still moving or has not reached the target yet
+    pub fn is_running(&self) -> bool {
+        self.speed != 0.0 || self.distance_to_go() != 0
+    }
+
+    /// The wrapped motor
+    pub fn motor(&mut self) -> &mut ULN2003<P1, P2, P3, P4, D, E, G, M, F, C, S> {
+        &mut self.motor
+    }
+
+    /// Give back the wrapped motor
+    pub fn into_inner(self) -> ULN2003<P1, P2, P3, P4, D, E, G, M, F, C, S> {
+        self.motor
+    }
+
+    /// steps at the current speed if a step is due
+    fn run_speed(&mut self, now_us: u64) -> Result<bool, StepError> {
+        if self.interval_us == 0 {
+            return Ok(false);
+        }
+        if self
+            .last_us
+            .is_some_and(|last| now_us.saturating_sub(last) < self.interval_us as u64)
+        {
+            return Ok(false);
+        }
+        self.last_us = Some(now_us);
+        self.motor.signal_motion(true)?;
+        self.motor.step_towards(self.target, self.dir)?;
+        Ok(true)
+    }
+
+    fn steps_to_stop(&self) -> i64 {
+        (self.speed * self.speed / (2.0 * self.acceleration)) as i64
+    }
+
+    /// the speed calculation of AccelStepper, called after every step and target change
+    fn compute_new_speed(&mut self) {
+        let half_steps = self.motor.drive_mode.half_steps() as u64;
+        let distance = self.distance_to_go();
+        // remaining distance in steps of the drive mode, rounded up
+        let distance = distance.signum() * distance.unsigned_abs().div_ceil(half_steps) as i64;
+        let steps_to_stop = self.steps_to_stop();
+        if distance == 0 && steps_to_stop <= 1 {
+            self.interval_us = 0;
+            self.speed = 0.0;
+            self.n = 0;
+            return;
+        }
+        if distance > 0 {
+            if self.n > 0 {
+                if steps_to_stop >= distance || self.dir == Direction::Reverse {
+                    self.n = -steps_to_stop;
+                }
+            } else if self.n < 0 && steps_to_stop < distance && self.dir == Direction::Normal {
+                self.n = -self.n;
+            }
+        } else if distance < 0 {
+            if self.n > 0 {
+                if steps_to_stop >= -distance || self.dir == Direction::Normal {
+                    self.n = -steps_to_stop;
+                }
+            } else if self.n < 0 && steps_to_stop < -distance && self.dir == Direction::Reverse {
+                self.n = -self.n;
+            }
+        }
+        if self.n == 0 {
+            self.cn = self.c0;
+            self.dir = if distance > 0 {
+                Direction::Normal
+            } else {
+                Direction::Reverse
+            };
+        } else {
+            self.cn -= 2.0 * self.cn / (4 * self.n + 1) as f32;
+            self.cn = self.cn.max(self.cmin);
+        }
+        self.n += 1;
+        self.interval_us = self.cn as u32;
+        self.speed = 1_000_000.0 / self.cn;
+        if self.dir == Direction::Reverse {
+            self.speed = -self.speed;
+        }
+    }
+}
+
+/// square root for positive values, as `f32::sqrt` needs std
+fn sqrt(x: f32) -> f32 {
+    if x <= 0.0 {
+        return 0.0;
+    }
+    // halving the exponent gives a rough guess, newton iterations refine it
+    let mut y = f32::from_bits((x.to_bits() >> 1) + 0x1fbd_1df5);
+    for _ in 0..4 {
+        y = 0.5 * (y + x / y);
+    }
+    y
+}
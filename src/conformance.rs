@@ -0,0 +1,145 @@
+//! Conformance checks for [`StepperMotor`](crate::StepperMotor) and [`BlockingStepperMotor`]
+//! implementations, enabled with the `test-utils` feature. Call [`check`] from a test of your
+//! own driver, e.g. on a mocked set of pins.
+
+use crate::{BlockingStepperMotor, Direction, DriveMode, StepError};
+
+/// The part of the [`StepperMotor`](crate::StepperMotor) contract an implementation broke
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Violation {
+    /// A method failed that should have worked
+    Failed(StepError),
+    /// A step didn't move the position by the half steps of the drive mode in the set direction
+    WrongStep,
+    /// A zero or negative step count moved the motor
+    ZeroMoveStepped,
+    /// [`stop`](crate::StepperMotor::stop), [`power_off`](crate::StepperMotor::power_off) or
+    /// [`reset_phase`](crate::StepperMotor::reset_phase) changed the position
+    PositionChanged,
+    /// A move ended early without any soft limits
+    StoppedEarly,
+    /// [`max_rpm`](crate::StepperMotor::max_rpm) is negative or not a number
+    InvalidMaxRpm,
+    /// A non-blocking move didn't finish or ended at the wrong position
+    PollMismatch,
+}
+
+impl From<StepError> for Violation {
+    fn from(e: StepError) -> Self {
+        Violation::Failed(e)
+    }
+}
+
+/// Drive `motor` through stepping in both directions, zero moves, stop, power off, phase reset
+/// and a non-blocking move, with `delay_ms` between blocking steps, and check the invariants of
+/// the trait. The motor needs a working delay, no soft limits and a position that doesn't wrap,
+/// and it moves by a few steps. Whether the normal direction counts up or down is taken from the
+/// first step, so inverted counting is fine.
+pub fn check(
+    motor: &mut (impl BlockingStepperMotor + ?Sized),
+    delay_ms: u32,
+) -> Result<(), Violation> {
+    let half_steps = match motor.drive_mode() {
+        DriveMode::HalfStep => 1,
+        DriveMode::FullStep | DriveMode::Wave => 2,
+    };
+    let rpm = motor.max_rpm();
+    if rpm.is_nan() || rpm < 0.0 {
+        return Err(Violation::InvalidMaxRpm);
+    }
+    // the first step may need a single half step to get onto the phases of the drive mode
+    motor.set_direction(Direction::Normal);
+    let start = motor.position();
+    let mut position = motor.step_for(1, delay_ms)?.final_position;
+    let normal = (position - start).signum();
+    if normal == 0 || position != motor.position() {
+        return Err(Violation::WrongStep);
+    }
+    for (dir, sign) in [(Direction::Normal, normal), (Direction::Reverse, -normal)] {
+        motor.set_direction(dir);
+        for _ in 0..4 {
+            let result = motor.step_for(1, delay_ms)?;
+            if result.stopped_early {
+                return Err(Violation::StoppedEarly);
+            }
+            if result.completed_steps != 1
+                || result.final_position != position + sign * half_steps
+                || result.final_position != motor.position()
+            {
+                return Err(Violation::WrongStep);
+            }
+            position = result.final_position;
+        }
+    }
+    for steps in [0, -3] {
+        let result = motor.step_for(steps, delay_ms)?;
+        if result.completed_steps != 0 || result.final_position != position {
+            return Err(Violation::ZeroMoveStepped);
+        }
+    }
+    motor.stop()?;
+    unchanged(motor, position)?;
+    motor.power_off()?;
+    unchanged(motor, position)?;
+    motor.reset_phase()?;
+    unchanged(motor, position)?;
+
+    motor.set_direction(Direction::Normal);
+    let start = motor.step_for(1, delay_ms)?.final_position;
+    motor.start_move(2, 1);
+    let mut now_us = 0;
+    while motor.poll(now_us)? {
+        now_us += 1;
+        if now_us > 1000 {
+            return Err(Violation::PollMismatch);
+        }
+    }
+    if motor.position() != start + normal * 2 * half_steps {
+        return Err(Violation::PollMismatch);
+    }
+    Ok(())
+}
+
+/// fails if the position is not `position` anymore
+fn unchanged(
+    motor: &mut (impl BlockingStepperMotor + ?Sized),
+    position: i64,
+) -> Result<(), Violation> {
+    if motor.position() == position {
+        Ok(())
+    } else {
+        Err(Violation::PositionChanged)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use embedded_hal::delay::DelayNs;
+
+    use super::*;
+    use crate::{NoPin, ULN2003};
+
+    struct NoWait;
+
+    impl DelayNs for NoWait {
+        fn delay_ns(&mut self, _ns: u32) {}
+    }
+
+    #[test]
+    fn driver_conforms() {
+        for mode in [DriveMode::HalfStep, DriveMode::FullStep, DriveMode::Wave] {
+            for inverted in [false, true] {
+                let mut motor = ULN2003::new(NoPin, NoPin, NoPin, NoPin, Some(NoWait));
+                motor.set_drive_mode(mode);
+                motor.set_count_inverted(inverted);
+                assert_eq!(
+                    check(&mut motor, 1),
+                    Ok(()),
+                    "{:?}, inverted: {}",
+                    mode,
+                    inverted
+                );
+            }
+        }
+    }
+}
@@ -3,13 +3,52 @@
 //! Platform-agnostic driver API for the 28BYJ-48 stepper motor used with the ULN2003 driver. Can be
 //! used on any platform for which implementations of the required
 //! [embedded-hal] traits are available.
+//!
+//! The driver has no `unwrap`, `expect`, `panic!` or indexing paths, which is enforced by the
+//! clippy lints denied below. Errors are always returned as [`StepError`].
 #![no_std]
 #![deny(missing_docs)]
+#![deny(
+    clippy::unwrap_used,
+    clippy::expect_used,
+    clippy::panic,
+    clippy::unreachable,
+    clippy::todo,
+    clippy::unimplemented,
+    clippy::indexing_slicing
+)]
+
+use core::convert::Infallible;
 
 use embedded_hal::delay::DelayNs;
 
-use embedded_hal::digital::{OutputPin, PinState};
 use embedded_hal::digital::PinState::{High, Low};
+use embedded_hal::digital::{ErrorType, InputPin, OutputPin, PinState, StatefulOutputPin};
+
+mod accel;
+#[cfg(any(test, feature = "test-utils"))]
+pub mod conformance;
+mod linear;
+mod multi;
+#[cfg(test)]
+mod tests;
+pub use accel::AccelStepper;
+pub use linear::LinearMove;
+pub use multi::MultiStepper;
+
+/// emits a trace message through the enabled `trace-log`, `trace-defmt` or `trace-rtt` backend
+macro_rules! trace {
+    ($($arg:tt)*) => {
+        #[cfg(feature = "trace-log")]
+        log::trace!($($arg)*);
+        #[cfg(feature = "trace-defmt")]
+        defmt::trace!($($arg)*);
+        #[cfg(feature = "trace-rtt")]
+        rtt_target::rprintln!($($arg)*);
+        #[cfg(not(any(feature = "trace-log", feature = "trace-defmt", feature = "trace-rtt")))]
+        let _ = ($($arg)*);
+    };
+}
 
 /// different positions of the motor.
 /// Depending on the state different pins have to be high
@@ -20,6 +59,7 @@ use embedded_hal::digital::PinState::{High, Low};
 /// |  3  |   |   | x | x | x |   |   |   |   |
 /// |  4  |   | x | x |   |   |   |   |   | x |
 #[derive(Copy, Clone, Debug)]
+#[cfg_attr(feature = "trace-defmt", derive(defmt::Format))]
 enum State {
     State0,
     State1,
@@ -32,7 +72,7 @@ enum State {
     State8,
 }
 
-fn get_pin_states(s: State) -> [PinState; 4] {
+const fn get_pin_states(s: State) -> [PinState; 4] {
     match s {
         State::State0 => [Low, Low, Low, Low],
         State::State1 => [Low, Low, Low, High],
@@ -46,7 +86,22 @@ fn get_pin_states(s: State) -> [PinState; 4] {
     }
 }
 
-fn get_next_state(s: State) -> State {
+const fn get_state(index: u8) -> Option<State> {
+    Some(match index {
+        0 => State::State0,
+        1 => State::State1,
+        2 => State::State2,
+        3 => State::State3,
+        4 => State::State4,
+        5 => State::State5,
+        6 => State::State6,
+        7 => State::State7,
+        8 => State::State8,
+        _ => return None,
+    })
+}
+
+const fn get_next_state(s: State) -> State {
     match s {
         State::State0 => State::State1,
         State::State1 => State::State2,
@@ -60,7 +115,93 @@ fn get_next_state(s: State) -> State {
     }
 }
 
-fn get_prev_state(s: State) -> State {
+/// the wire chart documented on [`State`], one row per wire with an `x` where it is high
+const WIRE_CHART: [&[u8; 9]; 4] = [b"      xxx", b"    xxx  ", b"  xxx    ", b" xx     x"];
+
+/// whether the `n`th column of a row of [`WIRE_CHART`] is marked
+const fn chart_marks(mut row: &[u8], mut n: u8) -> bool {
+    while let Some((mark, rest)) = row.split_first() {
+        if n == 0 {
+            return *mark == b'x';
+        }
+        n -= 1;
+        row = rest;
+    }
+    false
+}
+
+/// checks the phase table at compile time: every state drives the pins as in [`WIRE_CHART`],
+/// `get_next_state` and `get_prev_state` are inverses (except for leaving State0, which is never
+/// returned to), every half step switches exactly one coil and odd states energize one coil while
+/// even states energize two
+const fn phase_table_is_valid() -> bool {
+    let mut i = 0;
+    while i <= 8 {
+        let s = match get_state(i) {
+            Some(s) => s,
+            None => return false,
+        };
+        let [a, b, c, d] = get_pin_states(s);
+        let [w1, w2, w3, w4] = WIRE_CHART;
+        if high(a) != chart_marks(w1, i) as u8
+            || high(b) != chart_marks(w2, i) as u8
+            || high(c) != chart_marks(w3, i) as u8
+            || high(d) != chart_marks(w4, i) as u8
+        {
+            return false;
+        }
+        i += 1;
+    }
+    let mut i = 1;
+    while i <= 8 {
+        let s = match get_state(i) {
+            Some(s) => s,
+            None => return false,
+        };
+        if get_prev_state(get_next_state(s)) as u8 != i
+            || get_next_state(get_prev_state(s)) as u8 != i
+        {
+            return false;
+        }
+        let [a, b, c, d] = get_pin_states(s);
+        let [e, f, g, h] = get_pin_states(get_next_state(s));
+        let coils = high(a) + high(b) + high(c) + high(d);
+        let changes =
+            (high(a) ^ high(e)) + (high(b) ^ high(f)) + (high(c) ^ high(g)) + (high(d) ^ high(h));
+        if changes != 1 || coils != 2 - i % 2 {
+            return false;
+        }
+        i += 1;
+    }
+    matches!(get_next_state(State::State0), State::State1)
+        && matches!(get_pin_states(State::State0), [Low, Low, Low, Low])
+}
+
+const fn high(p: PinState) -> u8 {
+    matches!(p, High) as u8
+}
+
+const _: () = assert!(phase_table_is_valid(), "invalid phase table");
+
+const fn half_step(s: State, dir: Direction) -> State {
+    match dir {
+        Direction::Normal => get_next_state(s),
+        Direction::Reverse => get_prev_state(s),
+    }
+}
+
+/// the phase `s` is written as with the phase table shifted by `offset` half steps
+const fn shift_state(s: State, offset: u8) -> State {
+    match s {
+        State::State0 => State::State0,
+        _ => match get_state((s as u8 - 1 + offset % 8) % 8 + 1) {
+            Some(shifted) => shifted,
+            None => s,
+        },
+    }
+}
+
+const fn get_prev_state(s: State) -> State {
     match s {
         State::State0 => State::State8,
         State::State1 => State::State8,
@@ -74,112 +215,3124 @@ fn get_prev_state(s: State) -> State {
     }
 }
 
-/// Struct representing a Stepper motor with the 4 driver pins
-pub struct ULN2003<P1, P2, P3, P4, D>
-where
+/// Half steps per output shaft revolution of the 28BYJ-48 (64 steps times the 1:64 gearbox)
+pub const DEFAULT_STEPS_PER_REVOLUTION: u32 = 4096;
+/// Shortest delay between steps in ms the 28BYJ-48 can usually follow
+pub const DEFAULT_MIN_DELAY_MS: u32 = 1;
+
+mod sealed {
+    pub trait Sealed {
+        const AVAILABLE: bool;
+        fn try_delay_ms(&mut self, ms: u32) -> bool;
+        fn try_delay_us(&mut self, us: u32) -> bool;
+    }
+}
+
+/// Delay types accepted by [`ULN2003`]: every [`DelayNs`] and [`NoDelay`].
+/// This trait is sealed and can't be implemented outside of this crate.
+pub trait MaybeDelay: sealed::Sealed {}
+
+impl<T: DelayNs> sealed::Sealed for T {
+    const AVAILABLE: bool = true;
+
+    fn try_delay_ms(&mut self, ms: u32) -> bool {
+        self.delay_ms(ms);
+        true
+    }
+
+    fn try_delay_us(&mut self, us: u32) -> bool {
+        self.delay_us(us);
+        true
+    }
+}
+
+impl<T: DelayNs> MaybeDelay for T {}
+
+/// Use as delay type to create a motor without a delay, e.g. `ULN2003::new(.., None::<NoDelay>)`.
+/// The methods that need to wait between steps are then not available at all, instead of
+/// failing with [`StepError::MissingDelay`] at runtime. That includes
+/// [`BlockingStepperMotor`], only [`StepperMotor`] is implemented.
+pub struct NoDelay;
+
+impl sealed::Sealed for NoDelay {
+    const AVAILABLE: bool = false;
+
+    fn try_delay_ms(&mut self, _ms: u32) -> bool {
+        false
+    }
+
+    fn try_delay_us(&mut self, _us: u32) -> bool {
+        false
+    }
+}
+
+impl MaybeDelay for NoDelay {}
+
+/// Placeholder for optional pins that are not connected
+pub struct NoPin;
+
+impl ErrorType for NoPin {
+    type Error = Infallible;
+}
+
+impl OutputPin for NoPin {
+    fn set_low(&mut self) -> Result<(), Infallible> {
+        Ok(())
+    }
+
+    fn set_high(&mut self) -> Result<(), Infallible> {
+        Ok(())
+    }
+}
+
+impl InputPin for NoPin {
+    fn is_high(&mut self) -> Result<bool, Infallible> {
+        Ok(false)
+    }
+
+    fn is_low(&mut self) -> Result<bool, Infallible> {
+        Ok(true)
+    }
+}
+
+/// Writes all four coil pins at once, e.g. to turn the writes to an I2C or SPI port expander
+/// into a single bus transaction. Use it with [`ULN2003::from_pin_group`], otherwise the four
+/// pins are written one by one.
+pub trait PinGroup {
+    /// Set `in1` to `in4` to `states`
+    fn set_all(&mut self, states: [PinState; 4]) -> Result<(), StepError>;
+
+    /// Called once after every [`set_all`](Self::set_all), for backends that buffer the writes,
+    /// e.g. a shift register that only latches the outputs here. Does nothing by default.
+    fn flush(&mut self) -> Result<(), StepError> {
+        Ok(())
+    }
+}
+
+impl PinGroup for NoPin {
+    fn set_all(&mut self, _states: [PinState; 4]) -> Result<(), StepError> {
+        Ok(())
+    }
+}
+
+/// Struct representing a Stepper motor with the 4 driver pins, or a [`PinGroup`] writing them,
+/// and an optional enable pin switching the power of the driver board. `C` and `S` are the types
+/// of the position and persist callbacks, see [`ULN2003::with_position_callback`].
+pub struct ULN2003<
+    P1,
+    P2,
+    P3,
+    P4,
+    D,
+    E = NoPin,
+    G = NoPin,
+    M = NoPin,
+    F = NoPin,
+    C = fn(i64),
+    S = fn(i64),
+> where
     P1: OutputPin,
     P2: OutputPin,
     P3: OutputPin,
     P4: OutputPin,
-    D: DelayNs,
+    D: MaybeDelay,
+    E: OutputPin,
+    G: PinGroup,
+    M: OutputPin,
+    F: InputPin,
+    C: FnMut(i64),
+    S: FnMut(i64),
 {
     in1: P1,
     in2: P2,
     in3: P3,
     in4: P4,
+    group: G,
+    enable: E,
+    enabled: bool,
+    motion_flag: M,
+    signalling: bool,
+    flag_pending: bool,
+    fault: F,
+    fault_active: Option<PinState>,
     state: State,
     dir: Direction,
     delay: Option<D>,
+    position: i64,
+    steps_per_rev: u32,
+    min_delay_ms: u32,
+    max_single_delay_ms: u32,
+    pins: [PinState; 4],
+    step_delay_ms: Option<u32>,
+    drive_mode: DriveMode,
+    homing: Option<Homing>,
+    soft_start: bool,
+    delay_scale: (f32, f32),
+    motion: Option<Motion>,
+    velocity: Option<Velocity>,
+    max_accel: u32,
+    position_callback: Option<PositionCallback<C>>,
+    persist_callback: Option<PositionCallback<S>>,
+    limits: Option<(i64, i64)>,
+    accel_profile: AccelProfile,
+    backlash_steps: u32,
+    last_dir: Option<Direction>,
+    approach: Option<(Direction, u32)>,
+    position_mode: PositionMode,
+    mm_per_rev: f32,
+    chop: Option<Chop>,
+    pin_order: [usize; 4],
+    phase_offset: u8,
+    last_error: Option<StepError>,
+    move_start: i64,
+    last_move: Option<(i64, i64)>,
+    fraction: (i64, u32),
+    target: Option<i64>,
+    dither: Option<Dither>,
+    count_inverted: bool,
+    idle: IdleBehavior,
+    idle_on_zero_moves: bool,
+    tick_ratio: (u32, u32),
+    tick_acc: u64,
 }
 
-impl<P1: OutputPin, P2: OutputPin, P3: OutputPin, P4: OutputPin, D: DelayNs>
+impl<P1: OutputPin, P2: OutputPin, P3: OutputPin, P4: OutputPin, D: MaybeDelay>
     ULN2003<P1, P2, P3, P4, D>
 {
     /// Create a new StepperMotor from the 4 pins connected to te uln2003 driver.
-    /// The delay parameter is needed if you want to use the step_for function.
-    pub fn new(in1: P1, in2: P2, in3: P3, in4: P4, delay: Option<D>) -> Self {
+    /// The delay parameter is needed for the blocking moves like
+    /// [`step_for`](BlockingStepperMotor::step_for).
+    /// With [`NoDelay`] as delay type the functions waiting between steps are left out.
+    /// To share one delay between multiple motors create them without one and use a [`MultiStepper`].
+    ///
+    /// This is a `const fn`, so the driver can be placed in a `static` (e.g. inside a mutex shared
+    /// with a timer interrupt) as long as the pin and delay values can be created in a const context.
+    pub const fn new(in1: P1, in2: P2, in3: P3, in4: P4, delay: Option<D>) -> Self {
+        Self::build(in1, in2, in3, in4, delay, NoPin)
+    }
+
+    /// Create a new StepperMotor like [`new`](Self::new) and restore the state saved with
+    /// [`state_snapshot`](Self::state_snapshot). No pins are driven until the next step,
+    /// which continues from the saved phase. An invalid phase resets to the all-off state.
+    pub fn from_snapshot(
+        in1: P1,
+        in2: P2,
+        in3: P3,
+        in4: P4,
+        delay: Option<D>,
+        snapshot: MotionState,
+    ) -> Self {
+        let mut motor = Self::new(in1, in2, in3, in4, delay);
+        motor.state = get_state(snapshot.phase).unwrap_or(State::State0);
+        motor.position = snapshot.position;
+        motor.dir = snapshot.direction;
+        motor.drive_mode = snapshot.drive_mode;
+        motor.step_delay_ms = snapshot.step_delay_ms;
+        motor
+    }
+}
+
+impl<D: MaybeDelay, G: PinGroup> ULN2003<NoPin, NoPin, NoPin, NoPin, D, NoPin, G> {
+    /// Create a new StepperMotor writing all four pins at once through `group` instead of
+    /// separate pins, see [`PinGroup`]. Works like [`new`](ULN2003::new) otherwise.
+    pub const fn from_pin_group(group: G, delay: Option<D>) -> Self {
+        Self::build(NoPin, NoPin, NoPin, NoPin, delay, group)
+    }
+}
+
+impl<P1: OutputPin, P2: OutputPin, P3: OutputPin, P4: OutputPin, D: MaybeDelay, G: PinGroup>
+    ULN2003<P1, P2, P3, P4, D, NoPin, G>
+{
+    const fn build(in1: P1, in2: P2, in3: P3, in4: P4, delay: Option<D>, group: G) -> Self {
         Self {
             in1,
             in2,
             in3,
             in4,
+            group,
+            enable: NoPin,
+            enabled: false,
+            motion_flag: NoPin,
+            signalling: false,
+            flag_pending: false,
+            fault: NoPin,
+            fault_active: None,
             state: State::State0,
             dir: Direction::Normal,
             delay,
+            position: 0,
+            steps_per_rev: DEFAULT_STEPS_PER_REVOLUTION,
+            min_delay_ms: DEFAULT_MIN_DELAY_MS,
+            max_single_delay_ms: 0,
+            pins: [Low; 4],
+            step_delay_ms: None,
+            drive_mode: DriveMode::HalfStep,
+            homing: None,
+            soft_start: false,
+            delay_scale: (1.0, 1.0),
+            motion: None,
+            velocity: None,
+            max_accel: 0,
+            chop: None,
+            pin_order: [0, 1, 2, 3],
+            phase_offset: 0,
+            last_error: None,
+            move_start: 0,
+            last_move: None,
+            fraction: (0, 1),
+            target: None,
+            dither: None,
+            count_inverted: false,
+            idle: DEFAULT_IDLE,
+            idle_on_zero_moves: false,
+            tick_ratio: (1, 1),
+            tick_acc: 0,
+            position_callback: None,
+            persist_callback: None,
+            limits: None,
+            accel_profile: AccelProfile::Linear,
+            backlash_steps: 0,
+            last_dir: None,
+            approach: None,
+            position_mode: PositionMode::Linear,
+            mm_per_rev: 1.0,
         }
     }
 
-    fn apply_state(&mut self) -> Result<(), StepError> {
-        let states = get_pin_states(self.state);
-        set_state(&mut self.in1, states[0])?;
-        set_state(&mut self.in2, states[1])?;
-        set_state(&mut self.in3, states[2])?;
-        set_state(&mut self.in4, states[3])?;
-        Ok(())
+    /// Add a pin switching the power of the driver board, e.g. through a MOSFET, for zero idle
+    /// current. It is driven high before the coils are energized and low by
+    /// [`power_off`](StepperMotor::power_off) and [`disable`](ULN2003::disable).
+    pub fn with_enable_pin<E: OutputPin>(self, enable: E) -> ULN2003<P1, P2, P3, P4, D, E, G> {
+        let mut motor =
+            self.convert(|(_, flag, fault, cb, persist)| (enable, flag, fault, cb, persist));
+        motor.enabled = false;
+        motor
     }
 }
 
-/// gets returned if en Error happens while stepping
-#[derive(Debug)]
-pub struct StepError;
+impl<P1, P2, P3, P4, D, E, G> ULN2003<P1, P2, P3, P4, D, E, G>
+where
+    P1: OutputPin,
+    P2: OutputPin,
+    P3: OutputPin,
+    P4: OutputPin,
+    D: MaybeDelay,
+    E: OutputPin,
+    G: PinGroup,
+{
+    /// Add a pin that is driven high while the motor moves and low once it is idle again, e.g.
+    /// to trigger a camera or release a brake. It goes high with the first step of a move
+    /// (blocking, non-blocking, velocity mode, homing with [`poll_home`](ULN2003::poll_home) or
+    /// moves of [`MultiStepper`] and [`LinearMove`]) and low when the move ends, is cancelled or
+    /// fails. Moves without any steps don't touch it. Single steps like
+    /// [`step`](StepperMotor::step), [`step_isr`](ULN2003::step_isr) and
+    /// [`tick`](ULN2003::tick) and the steps of a [dithering hold](ULN2003::dither_hold) don't
+    /// drive it, as there is no end of the move to lower it again.
+    pub fn with_motion_flag_pin<M: OutputPin>(
+        self,
+        flag: M,
+    ) -> ULN2003<P1, P2, P3, P4, D, E, G, M> {
+        self.convert(|(enable, _, fault, cb, persist)| (enable, flag, fault, cb, persist))
+    }
+}
 
-impl<P1: OutputPin, P2: OutputPin, P3: OutputPin, P4: OutputPin, D: DelayNs>
-    StepperMotor for ULN2003<P1, P2, P3, P4, D>
+impl<P1, P2, P3, P4, D, E, G, M> ULN2003<P1, P2, P3, P4, D, E, G, M>
+where
+    P1: OutputPin,
+    P2: OutputPin,
+    P3: OutputPin,
+    P4: OutputPin,
+    D: MaybeDelay,
+    E: OutputPin,
+    G: PinGroup,
+    M: OutputPin,
 {
-    fn step(&mut self) -> Result<(), StepError> {
-        match self.dir {
-            Direction::Normal => self.state = get_next_state(self.state),
-            Direction::Reverse => self.state = get_prev_state(self.state),
+    /// Add the fault or overcurrent output of the driver board. It is checked before every step
+    /// and once it is at the `active` level the step fails with [`StepError::Fault`] and a
+    /// non-blocking move or the velocity mode is cancelled.
+    pub fn with_fault_pin<F: InputPin>(
+        self,
+        fault: F,
+        active: PinState,
+    ) -> ULN2003<P1, P2, P3, P4, D, E, G, M, F> {
+        let mut motor =
+            self.convert(|(enable, flag, _, cb, persist)| (enable, flag, fault, cb, persist));
+        motor.fault_active = Some(active);
+        motor
+    }
+}
+
+impl<P1, P2, P3, P4, D, E, G, M, F, C, S> ULN2003<P1, P2, P3, P4, D, E, G, M, F, C, S>
+where
+    P1: OutputPin,
+    P2: OutputPin,
+    P3: OutputPin,
+    P4: OutputPin,
+    D: MaybeDelay,
+    E: OutputPin,
+    G: PinGroup,
+    M: OutputPin,
+    F: InputPin,
+    C: FnMut(i64),
+    S: FnMut(i64),
+{
+    /// moves the motor into a struct with other extra pins or callbacks
+    fn convert<E2: OutputPin, M2: OutputPin, F2: InputPin, C2: FnMut(i64), S2: FnMut(i64)>(
+        self,
+        extras: impl FnOnce(Extras<E, M, F, C, S>) -> Extras<E2, M2, F2, C2, S2>,
+    ) -> ULN2003<P1, P2, P3, P4, D, E2, G, M2, F2, C2, S2> {
+        let (enable, motion_flag, fault, position_callback, persist_callback) = extras((
+            self.enable,
+            self.motion_flag,
+            self.fault,
+            self.position_callback,
+            self.persist_callback,
+        ));
+        ULN2003 {
+            in1: self.in1,
+            in2: self.in2,
+            in3: self.in3,
+            in4: self.in4,
+            group: self.group,
+            enable,
+            enabled: self.enabled,
+            motion_flag,
+            signalling: false,
+            flag_pending: false,
+            fault,
+            fault_active: self.fault_active,
+            state: self.state,
+            dir: self.dir,
+            delay: self.delay,
+            position: self.position,
+            steps_per_rev: self.steps_per_rev,
+            min_delay_ms: self.min_delay_ms,
+            max_single_delay_ms: self.max_single_delay_ms,
+            pins: self.pins,
+            step_delay_ms: self.step_delay_ms,
+            drive_mode: self.drive_mode,
+            homing: self.homing,
+            soft_start: self.soft_start,
+            delay_scale: self.delay_scale,
+            motion: self.motion,
+            velocity: self.velocity,
+            max_accel: self.max_accel,
+            chop: self.chop,
+            pin_order: self.pin_order,
+            phase_offset: self.phase_offset,
+            last_error: self.last_error,
+            move_start: self.move_start,
+            last_move: self.last_move,
+            fraction: self.fraction,
+            target: self.target,
+            dither: self.dither,
+            count_inverted: self.count_inverted,
+            idle: self.idle,
+            idle_on_zero_moves: self.idle_on_zero_moves,
+            tick_ratio: self.tick_ratio,
+            tick_acc: self.tick_acc,
+            position_callback,
+            persist_callback,
+            limits: self.limits,
+            accel_profile: self.accel_profile,
+            backlash_steps: self.backlash_steps,
+            last_dir: self.last_dir,
+            approach: self.approach,
+            position_mode: self.position_mode,
+            mm_per_rev: self.mm_per_rev,
         }
-        self.apply_state()?;
+    }
+
+    /// Power down the driver board with the enable pin. The coil pins and the phase are kept,
+    /// the next step powers the board up again. Does nothing without an enable pin.
+    pub fn disable(&mut self) -> Result<(), StepError> {
+        let result = set_state(&mut self.enable, Low);
+        self.record(result)?;
+        self.enabled = false;
+        Ok(())
+    }
+
+    /// Power up the driver board with the enable pin. Stepping does this automatically.
+    pub fn enable(&mut self) -> Result<(), StepError> {
+        let result = set_state(&mut self.enable, High);
+        self.record(result)?;
+        self.enabled = true;
         Ok(())
     }
 
-    fn step_for(&mut self, steps: i32, ms: u32) -> Result<(), StepError> {
-        if self.delay.is_none() {
-            return Err(StepError);
+    /// The latest error of any step or move since the last call, clearing it. Lets a supervising
+    /// task check for faults without checking the `Result` of every single step. The `Result`
+    /// stays the primary way to handle errors, only the most recent error is kept here.
+    pub fn take_last_error(&mut self) -> Option<StepError> {
+        self.last_error.take()
+    }
+
+    /// Save the logical state of the motor, e.g. to restore it after a power loss with
+    /// [`from_snapshot`](Self::from_snapshot)
+    pub fn state_snapshot(&self) -> MotionState {
+        MotionState {
+            position: self.position(),
+            phase: self.state as u8,
+            direction: self.phase_dir(self.dir),
+            drive_mode: self.drive_mode,
+            step_delay_ms: self.step_delay_ms,
         }
-        for _ in 0..steps {
-            self.step()?;
-            self.delay.as_mut().unwrap().delay_ms(ms);
+    }
+
+    /// The fraction of a half step [`rotate_fraction`](Self::rotate_fraction) carries to the
+    /// next call, as `(remainder, denominator)`: `remainder / denominator` half steps, with
+    /// `remainder` always below `denominator`. Exact, so it can be saved together with the
+    /// position and restored with [`set_fractional_remainder`](Self::set_fractional_remainder).
+    pub fn fractional_remainder(&self) -> (u32, u32) {
+        (self.fraction.0 as u32, self.fraction.1)
+    }
+
+    /// Set the fraction of a half step carried to the next
+    /// [`rotate_fraction`](Self::rotate_fraction) call with the same `denominator`, e.g. to
+    /// restore a saved [`fractional_remainder`](Self::fractional_remainder). Whole half steps in
+    /// `remainder` are dropped, a `denominator` of 0 is treated as 1.
+    pub fn set_fractional_remainder(&mut self, remainder: u32, denominator: u32) -> &mut Self {
+        let den = denominator.max(1);
+        self.fraction = ((remainder % den) as i64, den);
+        self
+    }
+
+    /// Set the value the position counter starts at, e.g. a position restored after a reboot.
+    /// This does not move the motor or drive any pins.
+    pub fn with_initial_position(&mut self, pos: i64) -> &mut Self {
+        self.set_position(pos);
+        self
+    }
+
+    /// Current position in half steps, also in the other drive modes.
+    /// Steps in the normal direction count up, reversed steps count down.
+    /// In [`PositionMode::Rotary`] it wraps to `0..steps_per_revolution`.
+    pub fn position(&self) -> i64 {
+        match self.position_mode {
+            PositionMode::Linear => self.position,
+            PositionMode::Rotary => self.position.rem_euclid(self.steps_per_rev.max(1) as i64),
         }
-        Ok(())
     }
 
-    fn set_direction(&mut self, dir: Direction) {
-        self.dir = dir;
+    /// Set whether the position wraps at a full revolution, see [`PositionMode`].
+    /// Soft limits always apply to the unwrapped position, so only use them in linear mode.
+    pub fn set_position_mode(&mut self, mode: PositionMode) -> &mut Self {
+        self.position_mode = mode;
+        self
     }
 
-    fn stop(&mut self) -> Result<(), StepError> {
+    /// The active position mode
+    pub fn position_mode(&self) -> PositionMode {
+        self.position_mode
+    }
+
+    /// Call `cb` with the new position every time the position moved by `every` half steps since
+    /// the last call. By default this takes a plain function pointer or a closure that doesn't
+    /// capture anything, [`with_position_callback`](Self::with_position_callback) sets a closure
+    /// with state and changes the type taken here to it. Set `every` to 0 to disable it again.
+    pub fn set_position_callback(&mut self, every: u32, cb: C) -> &mut Self {
+        self.position_callback = (every > 0).then_some(PositionCallback {
+            every,
+            cb,
+            last: self.position,
+        });
+        self
+    }
+
+    /// Call `cb` with the new position to store it, e.g. in an EEPROM, every time the position
+    /// moved by `min_interval_steps` half steps since the last call and once more at the end of
+    /// every move, so the stored position is never stale while the motor rests. Restore it with
+    /// [`with_initial_position`](Self::with_initial_position) after the next boot. Takes the
+    /// same kind of callback as [`set_position_callback`](Self::set_position_callback), see
+    /// [`with_persist_callback`](Self::with_persist_callback) for closures with state. Set
+    /// `min_interval_steps` to 0 to disable it again.
+    pub fn set_persist_callback(&mut self, min_interval_steps: u32, cb: S) -> &mut Self {
+        self.persist_callback = (min_interval_steps > 0).then_some(PositionCallback {
+            every: min_interval_steps,
+            cb,
+            last: self.position,
+        });
+        self
+    }
+
+    /// Like [`set_position_callback`](Self::set_position_callback) but for any `FnMut(i64)`,
+    /// e.g. a closure capturing a counter or a `&mut dyn FnMut(i64)`. The callback becomes part
+    /// of the motor type, so add it after the extra pins like
+    /// [`with_enable_pin`](ULN2003::with_enable_pin).
+    pub fn with_position_callback<C2: FnMut(i64)>(
+        self,
+        every: u32,
+        cb: C2,
+    ) -> ULN2003<P1, P2, P3, P4, D, E, G, M, F, C2, S> {
+        let last = self.position;
+        self.convert(|(enable, flag, fault, _, persist)| {
+            let cb = (every > 0).then_some(PositionCallback { every, cb, last });
+            (enable, flag, fault, cb, persist)
+        })
+    }
+
+    /// Like [`set_persist_callback`](Self::set_persist_callback) but for any `FnMut(i64)`, see
+    /// [`with_position_callback`](Self::with_position_callback).
+    pub fn with_persist_callback<S2: FnMut(i64)>(
+        self,
+        min_interval_steps: u32,
+        cb: S2,
+    ) -> ULN2003<P1, P2, P3, P4, D, E, G, M, F, C, S2> {
+        let last = self.position;
+        self.convert(|(enable, flag, fault, position, _)| {
+            let persist = (min_interval_steps > 0).then_some(PositionCallback {
+                every: min_interval_steps,
+                cb,
+                last,
+            });
+            (enable, flag, fault, position, persist)
+        })
+    }
+
+    /// States last written to the pins `in1` to `in4`
+    pub fn current_pin_states(&self) -> [PinState; 4] {
+        self.pins
+    }
+
+    /// Same as [`current_pin_states`](Self::current_pin_states), but `true` means the coil is energized
+    pub fn current_coils(&self) -> [bool; 4] {
+        self.pins.map(|s| s == High)
+    }
+
+    /// Predict which coils will be energized after a blocking move to `target` with
+    /// [`step_to`](Self::step_to), like [`current_coils`](Self::current_coils) would return them.
+    /// Takes the soft limits, the position mode and the [idle behavior](Self::set_idle_behavior)
+    /// into account without moving anything, e.g. to estimate the idle current beforehand.
+    pub fn predicted_hold_pattern(&self, target: i64) -> [bool; 4] {
+        let target = self.limit(self.resolve(target));
+        let moved = target != self.position;
+        let coils = match self.idle {
+            _ if !moved && !self.idle_on_zero_moves => return self.current_coils(),
+            IdleBehavior::Release => [Low; 4],
+            IdleBehavior::Hold => {
+                let state = match self.approach_from(target) {
+                    Some(overshoot) => {
+                        let state = self.phase_at(self.state, self.position, overshoot);
+                        self.phase_at(state, overshoot, target)
+                    }
+                    None => self.phase_at(self.state, self.position, target),
+                };
+                get_pin_states(shift_state(state, self.phase_offset))
+            }
+        };
+        self.pin_order
+            .map(|c| coils.get(c).is_some_and(|&s| s == High))
+    }
+
+    /// Whether any coil is currently energized.
+    /// False before the first step and after [`stop`](StepperMotor::stop) or [`power_off`](StepperMotor::power_off).
+    pub fn is_energized(&self) -> bool {
+        self.pins.contains(&High)
+    }
+
+    /// How strongly the motor is held right now, from the coils currently energized, e.g. to
+    /// budget the holding current. While [`hold_chopped`](Self::hold_chopped) runs this follows
+    /// the chopping.
+    pub fn energization_state(&self) -> EnergizationState {
+        match self.pins.iter().filter(|&&p| p == High).count() {
+            0 => EnergizationState::Released,
+            1 => EnergizationState::SingleCoil,
+            2 => EnergizationState::TwoCoils,
+            _ => EnergizationState::Custom,
+        }
+    }
+
+    /// Remap the coils for motors wired in a different order: `in1` to `in4` drive what
+    /// pins `order[0]` to `order[3]` would drive with the default order `[0, 1, 2, 3]`.
+    /// Fails with [`StepError::InvalidPinOrder`] if `order` is not a permutation of `0..4`,
+    /// see [`is_valid_pin_order`]. Takes effect with the next pin write.
+    pub fn set_pin_order(&mut self, order: [usize; 4]) -> Result<&mut Self, StepError> {
+        if !is_valid_pin_order(order) {
+            return Err(StepError::InvalidPinOrder);
+        }
+        self.pin_order = order;
+        Ok(self)
+    }
+
+    /// Shift the phase table by `offset` half steps (modulo 8), e.g. to align the magnetic
+    /// phases of two motors driving the same belt. The logical phase and position are unchanged,
+    /// only the coils energized for each phase move, in both directions alike. Full step and
+    /// wave mode keep energizing two and one coils. Takes effect with the next pin write.
+    pub fn set_phase_offset(&mut self, offset: u8) -> &mut Self {
+        self.phase_offset = offset % 8;
+        self
+    }
+
+    /// Low level test primitive for hardware bring-up: drive only pin `in1` to `in4` (index 0
+    /// to 3) to `state`, bypassing the phase sequence and the pin order, e.g. to check each
+    /// channel and its LED on its own. The other pins keep their state. Call
+    /// [`stop`](StepperMotor::stop) afterwards to get back to a coherent phase.
+    /// Fails with [`StepError::InvalidCoil`] for other indices.
+    pub fn set_coil(&mut self, index: usize, state: PinState) -> Result<(), StepError> {
+        let mut pins = self.pins;
+        *pins.get_mut(index).ok_or(StepError::InvalidCoil)? = state;
+        self.write_raw(pins)
+    }
+
+    /// Set how many steps make up a full revolution of the output shaft.
+    pub fn set_steps_per_revolution(&mut self, steps: u32) -> &mut Self {
+        self.steps_per_rev = steps;
+        self
+    }
+
+    /// Steps per full revolution of the output shaft
+    pub fn steps_per_revolution(&self) -> u32 {
+        self.steps_per_rev
+    }
+
+    /// Number of steps needed to turn the output shaft by `degrees`, rounded to the nearest step.
+    /// Negative angles give negative step counts. Doesn't move the motor.
+    pub fn steps_for_angle(&self, degrees: f32) -> i64 {
+        self.steps_for_revolutions(degrees / 360.0)
+    }
+
+    /// Number of steps needed for `revs` revolutions, rounded to the nearest step.
+    /// Negative revolutions give negative step counts. Doesn't move the motor.
+    pub fn steps_for_revolutions(&self, revs: f32) -> i64 {
+        round(revs * self.steps_per_rev as f32)
+    }
+
+    /// Net revolutions turned since position zero, including fractional turns.
+    /// Negative if the motor turned more in reverse than in the normal direction.
+    pub fn total_revolutions(&self) -> f32 {
+        self.position as f32 / self.steps_per_rev as f32
+    }
+
+    /// Current angle in thousandths of a degree without any float math, rounded like
+    /// [`rotate_millideg`](Self::rotate_millideg). Counts on past 360000 for multiple turns
+    /// unless in [`PositionMode::Rotary`], saturating at the `i32` range.
+    pub fn current_millideg(&self) -> i32 {
+        let mdeg = div_round(
+            self.position().saturating_mul(360_000),
+            self.steps_per_rev.max(1) as i64,
+        );
+        mdeg.clamp(i32::MIN as i64, i32::MAX as i64) as i32
+    }
+
+    /// Set how many millimeters a linear stage moves per revolution, e.g. the lead of a lead screw.
+    /// Used by [`step_to_mm`](Self::step_to_mm) and [`current_mm`](Self::current_mm), 1.0 by default.
+    /// Fails with [`StepError::InvalidRatio`] and keeps the old ratio if `mm_per_rev` is zero,
+    /// infinite or NaN. Negative ratios flip the direction of the millimeter scale.
+    pub fn set_linear_ratio(&mut self, mm_per_rev: f32) -> Result<&mut Self, StepError> {
+        if mm_per_rev == 0.0 || !mm_per_rev.is_finite() {
+            return Err(StepError::InvalidRatio);
+        }
+        self.mm_per_rev = mm_per_rev;
+        Ok(self)
+    }
+
+    /// Current position in millimeters, see [`set_linear_ratio`](Self::set_linear_ratio)
+    pub fn current_mm(&self) -> f32 {
+        self.position() as f32 / self.steps_per_rev as f32 * self.mm_per_rev
+    }
+
+    /// Set the shortest delay between steps in ms your motor can follow under its load.
+    /// This is used to calculate the maximum speed, shorter delays are rejected with
+    /// [`StepError::TooFast`] to avoid silently skipping steps. Set it to 0 to disable the check.
+    pub fn set_min_delay_ms(&mut self, ms: u32) -> &mut Self {
+        self.min_delay_ms = ms;
+        self
+    }
+
+    /// Split waits longer than `ms` into several calls of the delay provider, for HALs that
+    /// misbehave with long single delays. 0 (the default) waits with a single call.
+    pub fn set_max_single_delay_ms(&mut self, ms: u32) -> &mut Self {
+        self.max_single_delay_ms = ms;
+        self
+    }
+
+    /// Set the steps per revolution and the minimum delay to the presets of a motor variant,
+    /// see [`MotorProfile`]
+    pub fn apply_profile(&mut self, profile: MotorProfile) -> &mut Self {
+        self.steps_per_rev = profile.steps_per_revolution();
+        self.min_delay_ms = profile.min_delay_ms();
+        self
+    }
+
+    /// Shortest delay between steps in ms the motor is configured to follow
+    pub fn min_delay_ms(&self) -> u32 {
+        self.min_delay_ms
+    }
+
+    /// Set the stepping direction. Returns `self` so configuration calls can be chained.
+    pub fn set_direction(&mut self, dir: Direction) -> &mut Self {
+        let phase_dir = self.phase_dir(dir);
+        if phase_dir != self.dir {
+            trace!("direction changed to {:?}", dir);
+        }
+        self.dir = phase_dir;
+        self
+    }
+
+    /// Count the position down when stepping in the normal direction and up in reverse, e.g.
+    /// for a motor mounted backwards. Only the counting changes, a direction still turns the
+    /// motor the same way. Positions, targets and limits are all in the inverted coordinates.
+    pub fn set_count_inverted(&mut self, inverted: bool) -> &mut Self {
+        if inverted != self.count_inverted {
+            // keep turning the same way
+            self.dir = self.dir.reversed();
+            self.last_dir = self.last_dir.map(Direction::reversed);
+            self.count_inverted = inverted;
+        }
+        self
+    }
+
+    /// Set how the coils are driven. Positions stay counted in half steps in every mode.
+    pub fn set_drive_mode(&mut self, mode: DriveMode) -> &mut Self {
+        self.drive_mode = mode;
+        self
+    }
+
+    /// The active drive mode
+    pub fn drive_mode(&self) -> DriveMode {
+        self.drive_mode
+    }
+
+    /// Steps of the active drive mode per full step: 2 in half step mode, 1 in full step and
+    /// wave mode. The position is always counted in half steps.
+    pub fn steps_per_full_step(&self) -> u8 {
+        (2 / self.drive_mode.half_steps()) as u8
+    }
+
+    /// Set the gearbox backlash in steps. [`step_segments`](Self::step_segments) does that many
+    /// uncounted steps to take up the slack whenever the direction changes. 0 by default.
+    pub fn set_backlash_steps(&mut self, steps: u32) -> &mut Self {
+        self.backlash_steps = steps;
+        self
+    }
+
+    /// Always do the last steps of [`step_to`](Self::step_to),
+    /// [`step_to_angle`](Self::step_to_angle) and the other moves to a target in `dir`, so
+    /// repeated moves end with the same side of the gearbox loaded. Moves that would arrive from
+    /// the other side overshoot the target by `margin_steps` half steps (within the soft limits)
+    /// and come back. `None` disables it again, which is the default.
+    pub fn set_unidirectional_approach(
+        &mut self,
+        dir: Option<Direction>,
+        margin_steps: u32,
+    ) -> &mut Self {
+        self.approach = dir.map(|dir| (dir, margin_steps));
+        self
+    }
+
+    /// Set the shape of the ramps of [`step_for_ramped`](Self::step_for_ramped)
+    pub fn set_accel_profile(&mut self, profile: AccelProfile) -> &mut Self {
+        self.accel_profile = profile;
+        self
+    }
+
+    /// Energize the coils gradually when stepping from the fully powered off state, which avoids
+    /// the click and small jerk of switching on at once. Takes about 3.5 ms and needs a delay,
+    /// without one the coils are switched on directly. Off by default.
+    pub fn set_soft_start(&mut self, enabled: bool) -> &mut Self {
+        self.soft_start = enabled;
+        self
+    }
+
+    /// Set what happens with the coils after a blocking move or a non-blocking move finished.
+    /// Holding by default, releasing with the `release-on-idle` feature. Moves of
+    /// [`MultiStepper`] and [`LinearMove`] apply it too, through
+    /// [`StepperMotor::motion_finished`].
+    pub fn set_idle_behavior(&mut self, idle: IdleBehavior) -> &mut Self {
+        self.idle = idle;
+        self
+    }
+
+    /// What happens with the coils after a move
+    pub fn idle_behavior(&self) -> IdleBehavior {
+        self.idle
+    }
+
+    /// Moves of zero steps (e.g. [`step_to`](Self::step_to) the current position) don't touch
+    /// the pins at all by default, and don't need a valid delay either. With this enabled they still apply the
+    /// [idle behavior](Self::set_idle_behavior), so they can be used to hold or release.
+    pub fn set_idle_on_zero_moves(&mut self, enabled: bool) -> &mut Self {
+        self.idle_on_zero_moves = enabled;
+        self
+    }
+
+    /// Scale the delay between steps depending on the direction, e.g. to move slower against
+    /// gravity. Applies to all blocking moves, the default of 1.0 keeps the given delays.
+    pub fn set_direction_delay_scale(
+        &mut self,
+        normal_scale: f32,
+        reverse_scale: f32,
+    ) -> &mut Self {
+        self.delay_scale = (normal_scale, reverse_scale);
+        self
+    }
+
+    /// Reset the runtime configuration to the defaults of [`new`](Self::new): normal direction,
+    /// no count inversion, half step mode, no soft limits, no speed, no delay scaling, no soft
+    /// start, linear ramps, the default [idle behavior](IdleBehavior) also for zero moves, no
+    /// velocity acceleration limit, no unidirectional approach and one step per tick.
+    ///
+    /// Kept are the position (in the coordinates without inversion from then on), the phase, the
+    /// position mode, the fractional remainder, the wiring (pin order, phase offset and the extra
+    /// pins), the callbacks, the motor calibration (steps per revolution, minimum delay, longest
+    /// single delay, backlash and linear ratio) as well as running moves and holds. No pins are
+    /// driven.
+    pub fn reset_config(&mut self) -> &mut Self {
+        self.set_count_inverted(false);
+        self.dir = Direction::default();
+        self.drive_mode = DriveMode::default();
+        self.limits = None;
+        self.step_delay_ms = None;
+        self.delay_scale = (1.0, 1.0);
+        self.soft_start = false;
+        self.accel_profile = AccelProfile::default();
+        self.idle = DEFAULT_IDLE;
+        self.idle_on_zero_moves = false;
+        self.tick_ratio = (1, 1);
+        self.tick_acc = 0;
+        self.max_accel = 0;
+        self.approach = None;
+        self
+    }
+
+    /// Set the speed in revolutions per minute used by [`run`](Self::run).
+    /// Fails with [`StepError::TooFast`] for speeds above [`max_rpm`](StepperMotor::max_rpm).
+    /// The delay is rounded to whole ms, so the motor turns at the nearest speed that allows.
+    pub fn set_speed_rpm(&mut self, rpm: f32) -> Result<&mut Self, StepError> {
+        if rpm > StepperMotor::max_rpm(self) {
+            return Err(StepError::TooFast);
+        }
+        self.set_step_delay_ms(rpm_to_delay_ms(rpm, self.steps_per_mode_rev()))
+    }
+
+    /// Set the delay between steps in ms used by [`run`](Self::run).
+    /// Fails with [`StepError::TooFast`] for delays below [`min_delay_ms`](Self::min_delay_ms).
+    pub fn set_step_delay_ms(&mut self, ms: u32) -> Result<&mut Self, StepError> {
+        if ms < self.min_delay_ms {
+            return Err(StepError::TooFast);
+        }
+        self.step_delay_ms = Some(ms);
+        Ok(self)
+    }
+
+    /// Energize the coils again after [`power_off`](StepperMotor::power_off) with the phase the
+    /// motor was left at, so the rotor doesn't move. Uses the soft start if enabled.
+    pub fn wake(&mut self) -> Result<(), StepError> {
+        self.energize()
+    }
+
+    /// Reset the electrical phase to the initial all-off state without changing the position counter.
+    /// The next step energizes the first phase again, so the rotor may shift by up to a few steps
+    /// relative to the tracked position.
+    pub fn realign(&mut self) -> Result<(), StepError> {
         self.state = State::State0;
-        self.apply_state()?;
+        self.chop = None;
+        self.dither = None;
+        self.apply_state()
+    }
+
+    /// Hold with maximum torque by energizing two adjacent coils. If the current phase only
+    /// energizes one coil this does a half step in the configured direction (counted in the
+    /// position) onto the nearest two coil phase. From the all-off state these are two half
+    /// steps, the first one energizes the first phase. Draws about twice the current of a single
+    /// coil hold. Fails like [`step`](StepperMotor::step) at the soft limits or while a
+    /// non-blocking move is in progress.
+    pub fn hold_strong(&mut self) -> Result<(), StepError> {
+        if self.is_moving() {
+            return self.record(Err(StepError::MoveInProgress));
+        }
+        while !self.mode_uses(DriveMode::FullStep, self.state) {
+            self.half_step_in(self.dir)?;
+        }
+        self.apply_state()
+    }
+
+    /// Do at most one half step in the configured direction so the motor rests on a phase with a
+    /// single energized coil, for predictable holding torque and current. The step is counted
+    /// in the position. From the all-off state this energizes the first phase. Fails like
+    /// [`step`](StepperMotor::step) at the soft limits or while a non-blocking move is in
+    /// progress.
+    pub fn align_to_full_step(&mut self) -> Result<(), StepError> {
+        if self.is_moving() {
+            return self.record(Err(StepError::MoveInProgress));
+        }
+        if !self.mode_uses(DriveMode::Wave, self.state) {
+            let dir = match self.state {
+                State::State0 => self.phase_dir(Direction::Normal),
+                _ => self.dir,
+            };
+            self.half_step_in(dir)?;
+        }
         Ok(())
     }
-}
 
-fn set_state<P: OutputPin>(pin: &mut P, state: PinState) -> Result<(), StepError> {
-    match pin.set_state(state) {
-        Ok(_) => Ok(()),
-        Err(_) => Err(StepError),
+    /// Do a single step in `dir` without changing the configured direction and return the new
+    /// phase index, 1 to 8 like [`MotionState::phase`]. Useful for tracing or custom sequencers.
+    pub fn step_and_report(&mut self, dir: Direction) -> Result<u8, StepError> {
+        if self.is_moving() {
+            return self.record(Err(StepError::MoveInProgress));
+        }
+        self.step_in(self.phase_dir(dir))?;
+        Ok(self.state as u8)
     }
-}
 
-/// trait to prevent having to pass around the struct with all the generic arguments
-pub trait StepperMotor {
-    /// Do a single step
-    fn step(&mut self) -> Result<(), StepError>;
-    /// Do multiple steps with a given delay in ms
-    fn step_for(&mut self, steps: i32, delay: u32) -> Result<(), StepError>;
-    /// Set the stepping direction
-    fn set_direction(&mut self, dir: Direction);
-    /// Stoping sets all pins low
-    fn stop(&mut self) -> Result<(), StepError>;
-}
+    /// Net change of the phase since `prior_phase`, taken from [`MotionState::phase`] or
+    /// [`step_and_report`](Self::step_and_report), in half steps in the direction the position
+    /// counts. The phases repeat every 8 half steps, so after a move of `n` half steps this is
+    /// `n` wrapped into `-3..=4`, e.g. to check for lost steps in custom sequencers.
+    /// Returns 0 if either phase is the all-off phase 0, which is not part of the sequence.
+    pub fn phase_delta_since(&self, prior_phase: u8) -> i32 {
+        let current = self.state as u8;
+        if !(1..=8).contains(&prior_phase) || current == 0 {
+            return 0;
+        }
+        let delta = current as i32 - prior_phase as i32;
+        let delta = if self.count_inverted { -delta } else { delta }.rem_euclid(8);
+        if delta > 4 {
+            delta - 8
+        } else {
+            delta
+        }
+    }
 
-/// Direction the motor turns in. Just reverses the order of the internal states.
-pub enum Direction {
-    /// Default direction
-    Normal,
-    /// Reversed direction
-    Reverse,
+    /// Half steps [`align_to_full_step`](Self::align_to_full_step) would do to reach a phase
+    /// with a single energized coil: 0 if the motor already rests on one, otherwise 1.
+    /// Also 1 before the first step.
+    pub fn steps_to_full_step(&self) -> u8 {
+        if self.mode_uses(DriveMode::Wave, self.state) {
+            0
+        } else {
+            1
+        }
+    }
+
+    /// Minimal version of [`step`](StepperMotor::step) to be called from a timer interrupt.
+    /// It only advances the phase, writes the pins and counts the position (respecting the soft
+    /// limits and the fault pin). There is no delay, soft start, tracing or position callback, not
+    /// even on errors, and nothing allocates or blocks, so the time spent in the interrupt only
+    /// depends on the pin reads and writes. Errors are still kept for
+    /// [`take_last_error`](Self::take_last_error).
+    pub fn step_isr(&mut self) -> Result<(), StepError> {
+        let result = self.isr_step();
+        if let Err(e) = result {
+            self.last_error = Some(e);
+        }
+        result
+    }
+
+    /// `step_isr` without recording the error, nothing here traces
+    fn isr_step(&mut self) -> Result<(), StepError> {
+        self.check_limits(self.dir)?;
+        if let Some(active) = self.fault_active {
+            if pin_is(&mut self.fault, active)? {
+                self.motion = None;
+                self.velocity = None;
+                return Err(StepError::Fault);
+            }
+        }
+        let (state, half_steps) = self.next_phase(self.dir);
+        self.state = state;
+        self.last_dir = Some(self.dir);
+        self.last_move = None;
+        self.position = offset(self.position, self.dir, half_steps as i64);
+        self.write_coils(self.ordered(self.coil_states()))
+    }
+
+    /// Set how many steps [`tick`](Self::tick) does per tick as the fraction `steps / ticks`,
+    /// e.g. `(1, 3)` for one step every third tick. Like gears between motors driven by the same
+    /// tick. Resets the fractional steps collected so far. `ticks` of 0 is treated as 1.
+    pub fn set_steps_per_tick(&mut self, steps: u32, ticks: u32) -> &mut Self {
+        self.tick_ratio = (steps, ticks.max(1));
+        self.tick_acc = 0;
+        self
+    }
+
+    /// Advance by `ticks` ticks of an external clock, doing as many steps in the configured
+    /// direction as the [ratio](Self::set_steps_per_tick) allows. The remaining fraction of a
+    /// step is carried over, so the ratio stays exact in the long run. The steps are done
+    /// right away without any delay, so for more than one step per call keep the ticks short.
+    /// Returns the number of steps done.
+    pub fn tick(&mut self, ticks: u32) -> Result<u32, StepError> {
+        let (steps, per) = self.tick_ratio;
+        let acc = self.tick_acc.saturating_add(ticks as u64 * steps as u64);
+        let due = (acc / per as u64).min(u32::MAX as u64) as u32;
+        self.tick_acc = acc - due as u64 * per as u64;
+        for _ in 0..due {
+            self.step_in(self.dir)?;
+        }
+        Ok(due)
+    }
+
+    /// Do a single step in the given direction without changing the position counter.
+    /// Useful for backlash takeup or alignment moves that should not count as motion.
+    pub fn step_uncounted(&mut self, dir: Direction) -> Result<(), StepError> {
+        self.advance(self.phase_dir(dir))?;
+        Ok(())
+    }
+
+    /// Plan the shortest move to the absolute shaft angle `degrees` (0 is the angle at position
+    /// zero) without moving. Returns the direction and the number of half steps the move takes,
+    /// turning at most half a revolution either way.
+    pub fn plan_move_to_angle(&self, degrees: f32) -> (Direction, i64) {
+        let delta = self.nearest_turn(self.steps_for_angle(degrees));
+        if delta < 0 {
+            (self.phase_dir(Direction::Reverse), -delta)
+        } else {
+            (self.phase_dir(Direction::Normal), delta)
+        }
+    }
+
+    /// Delays in ms after each step of [`step_for_ramped`](Self::step_for_ramped) with the same
+    /// arguments and the configured acceleration profile, without moving the motor. The direction
+    /// delay scale is applied on top of these when moving.
+    pub fn compute_ramp_delays(
+        &self,
+        steps: i32,
+        start_delay_ms: u32,
+        min_delay_ms: u32,
+        accel_steps: u32,
+        decel_steps: u32,
+    ) -> impl Iterator<Item = u32> {
+        let steps = steps.max(0) as u32;
+        let profile = self.accel_profile;
+        (0..steps).map(move |i| {
+            ramp_delay_ms(
+                i,
+                steps,
+                start_delay_ms,
+                min_delay_ms,
+                accel_steps,
+                decel_steps,
+                profile,
+            )
+        })
+    }
+
+    /// Time in ms [`run`](Self::run) with `steps` would spend waiting, with the configured step
+    /// delay and the delay scale of the configured direction, without moving the motor. 0 if no
+    /// speed is configured. Only the waits are counted, writing the pins adds a few us per step
+    /// on top, so the real move takes slightly longer. Saturates at `u32::MAX`.
+    pub fn estimate_move_time_ms(&self, steps: i32) -> u32 {
+        let ms = self.step_delay_ms.unwrap_or(0);
+        move_duration_ms(steps, self.scale_delay(self.dir, ms))
+    }
+
+    /// Like [`estimate_move_time_ms`](Self::estimate_move_time_ms) for
+    /// [`step_for_ramped`](Self::step_for_ramped) with the same arguments, adding up the delays
+    /// of [`compute_ramp_delays`](Self::compute_ramp_delays).
+    pub fn estimate_ramped_move_time_ms(
+        &self,
+        steps: i32,
+        start_delay_ms: u32,
+        min_delay_ms: u32,
+        accel_steps: u32,
+        decel_steps: u32,
+    ) -> u32 {
+        self.compute_ramp_delays(
+            steps,
+            start_delay_ms,
+            min_delay_ms,
+            accel_steps,
+            decel_steps,
+        )
+        .fold(0, |total: u32, ms| {
+            total.saturating_add(self.scale_delay(self.dir, ms))
+        })
+    }
+
+    /// Suggested jog step sizes for manual control panels: one step, one degree and an eighth
+    /// of a revolution, in steps of the active drive mode
+    pub fn jog_granularities(&self) -> [u32; 3] {
+        let half_steps = self.drive_mode.half_steps();
+        [
+            1,
+            (self.steps_for_angle(1.0) as u32 / half_steps).max(1),
+            (self.steps_per_rev / 8 / half_steps).max(1),
+        ]
+    }
+
+    /// steps of the active drive mode per revolution
+    fn steps_per_mode_rev(&self) -> u32 {
+        self.steps_per_rev / self.drive_mode.half_steps()
+    }
+
+    /// checks that a blocking move with `ms` between steps can be done and remembers where it
+    /// starts for `undo_last_move`. An `empty` move does no steps, so it needs no delay.
+    fn begin_move(&mut self, ms: u32, empty: bool) -> Result<(), StepError> {
+        self.move_start = self.position;
+        if self.is_moving() {
+            self.record(Err(StepError::MoveInProgress))
+        } else if empty {
+            Ok(())
+        } else if !self.has_delay() {
+            self.record(Err(StepError::MissingDelay))
+        } else if ms < self.min_delay_ms {
+            self.record(Err(StepError::TooFast))
+        } else {
+            trace!("blocking move started at {} with {} ms", self.position, ms);
+            // the flag is raised by the first step
+            self.flag_pending = true;
+            Ok(())
+        }
+    }
+
+    /// fails with `Fault` and cancels any non-blocking motion if the fault pin is active
+    fn check_fault(&mut self) -> Result<(), StepError> {
+        let Some(active) = self.fault_active else {
+            return Ok(());
+        };
+        let fault = pin_is(&mut self.fault, active);
+        if self.record(fault)? {
+            trace!("fault pin active at {}", self.position);
+            self.motion = None;
+            self.velocity = None;
+            return self.record(Err(StepError::Fault));
+        }
+        Ok(())
+    }
+
+    /// drives the motion flag pin, only writing it when the signal changes
+    fn signal_motion(&mut self, active: bool) -> Result<(), StepError> {
+        let result = self.write_motion_flag(active);
+        self.record(result)
+    }
+
+    /// `signal_motion` without recording the error, for `record` itself
+    fn write_motion_flag(&mut self, active: bool) -> Result<(), StepError> {
+        if self.signalling != active {
+            set_state(&mut self.motion_flag, PinState::from(active))?;
+            self.signalling = active;
+        }
+        Ok(())
+    }
+
+    /// waits `ms` scaled by the delay scale for `dir` after a step
+    fn wait_after_step(&mut self, dir: Direction, ms: u32) -> Result<(), StepError> {
+        self.wait_ms(self.scale_delay(dir, ms))
+    }
+
+    /// scales a delay by the delay scale for `dir`
+    fn scale_delay(&self, dir: Direction, delay: u32) -> u32 {
+        let scale = match self.phase_dir(dir) {
+            Direction::Normal => self.delay_scale.0,
+            Direction::Reverse => self.delay_scale.1,
+        };
+        if scale == 1.0 {
+            delay
+        } else {
+            round_u32(delay as f32 * scale)
+        }
+    }
+
+    /// waits `ms` using the delay provider, fails if there is none
+    fn wait_ms(&mut self, ms: u32) -> Result<(), StepError> {
+        self.wait_parts(ms, self.max_single_delay_ms, D::try_delay_ms)
+    }
+
+    /// waits `total` with `wait`, in parts of at most `max` if it isn't 0
+    fn wait_parts(
+        &mut self,
+        total: u32,
+        max: u32,
+        wait: fn(&mut D, u32) -> bool,
+    ) -> Result<(), StepError> {
+        let max = if max == 0 { u32::MAX } else { max };
+        let mut left = total;
+        loop {
+            let part = left.min(max);
+            if !self.delay.as_mut().is_some_and(|d| wait(d, part)) {
+                return self.record(Err(StepError::MissingDelay));
+            }
+            left -= part;
+            if left == 0 {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Start a non-blocking move of `steps` steps in the configured direction with `delay_us`
+    /// between steps. The steps are done by calling [`poll`](Self::poll) regularly.
+    pub fn start_move(&mut self, steps: i32, delay_us: u32) {
+        let half_steps = steps.max(0) as i64 * self.drive_mode.half_steps() as i64;
+        let target = offset(self.position, self.dir, half_steps);
+        self.start_motion(target, delay_us);
+    }
+
+    /// Start a non-blocking move to the absolute position `target` with `delay_us` between steps.
+    ///
+    /// This can be called while another move is in progress to change the target on the fly, e.g.
+    /// to follow a moving setpoint. The direction and remaining steps are recomputed on the next
+    /// poll and the step timing continues without a pause. There is no deceleration before
+    /// reversing, so with heavy loads keep the delay long enough for an instant reversal, and
+    /// note that gearbox backlash is not compensated when the direction changes.
+    /// In [`PositionMode::Rotary`] the motor takes the shorter way around.
+    pub fn start_move_to(&mut self, target: i64, delay_us: u32) {
+        self.start_motion(self.resolve(target), delay_us);
+    }
+
+    /// starts a non-blocking move to a target in unwrapped positions
+    fn start_motion(&mut self, target: i64, delay_us: u32) {
+        let next_us = self.motion.as_ref().and_then(|m| m.next_us);
+        let target = self.limit(target);
+        trace!("move from {} to {} started", self.position, target);
+        self.target = Some(target);
+        self.dither = None;
+        self.velocity = None;
+        self.motion = Some(Motion {
+            target,
+            interval_us: delay_us,
+            next_us,
+        });
+    }
+
+    /// Drive a move started with [`start_move`](Self::start_move),
+    /// [`start_move_to`](Self::start_move_to) or [`set_velocity`](Self::set_velocity).
+    /// `now_us` is the current time in microseconds from any monotonic clock. Does a step if it
+    /// is due and returns whether the move is still in progress. Without a move it switches the
+    /// coils of [`hold_chopped`](Self::hold_chopped).
+    pub fn poll(&mut self, now_us: u64) -> Result<bool, StepError> {
+        if self.velocity.is_some() {
+            return self.poll_velocity(now_us);
+        }
+        let Some(motion) = self.motion.as_mut() else {
+            self.poll_dither(now_us)?;
+            self.poll_chop(now_us)?;
+            return Ok(false);
+        };
+        let target = motion.target;
+        if motion.next_us.is_some_and(|next| now_us < next) {
+            return Ok(true);
+        }
+        if self.position != target {
+            motion.next_us = Some(now_us.saturating_add(motion.interval_us as u64));
+        }
+        self.advance_motion(target)
+    }
+
+    /// Turn continuously at `steps_per_s` steps of the active drive mode per second, negative
+    /// speeds in reverse, e.g. for a jog wheel. Ends a non-blocking move in progress, the steps
+    /// are done by [`poll`](Self::poll) until the velocity is set to 0 or the soft limits are
+    /// reached. With [`set_max_accel`](Self::set_max_accel) the actual velocity ramps towards
+    /// this one instead of jumping.
+    pub fn set_velocity(&mut self, steps_per_s: i32) -> &mut Self {
+        self.motion = None;
+        self.target = None;
+        self.dither = None;
+        let velocity = self.velocity.get_or_insert(Velocity {
+            target: 0.0,
+            current: 0.0,
+            last_us: None,
+            last_step_us: None,
+        });
+        velocity.target = steps_per_s as f32;
+        self
+    }
+
+    /// Limit how fast the velocity of [`set_velocity`](Self::set_velocity) changes, in steps per
+    /// second squared. 0 (the default) changes it instantly.
+    pub fn set_max_accel(&mut self, steps_per_s2: u32) -> &mut Self {
+        self.max_accel = steps_per_s2;
+        self
+    }
+
+    /// The commanded and the current velocity of [`set_velocity`](Self::set_velocity) in
+    /// steps per second, both 0 outside of the velocity mode
+    pub fn velocity(&self) -> (f32, f32) {
+        self.velocity
+            .as_ref()
+            .map_or((0.0, 0.0), |v| (v.target, v.current))
+    }
+
+    /// ramps the velocity towards the commanded one and steps if a step is due
+    fn poll_velocity(&mut self, now_us: u64) -> Result<bool, StepError> {
+        let max_accel = self.max_accel as f32;
+        let Some(v) = self.velocity.as_mut() else {
+            return Ok(false);
+        };
+        let dt = v.last_us.map_or(0, |last| now_us.saturating_sub(last)) as f32 / 1_000_000.0;
+        v.last_us = Some(now_us);
+        v.current = if max_accel > 0.0 {
+            let max_change = max_accel * dt;
+            v.current + (v.target - v.current).clamp(-max_change, max_change)
+        } else {
+            v.target
+        };
+        let current = v.current;
+        if current == 0.0 {
+            if v.target == 0.0 {
+                self.velocity = None;
+                self.finish_move(true)?;
+                return Ok(false);
+            }
+            return Ok(true);
+        }
+        let interval_us = (1_000_000.0 / current.abs()) as u64;
+        if v.last_step_us
+            .is_some_and(|last| now_us.saturating_sub(last) < interval_us)
+        {
+            return Ok(true);
+        }
+        v.last_step_us = Some(now_us);
+        let dir = if current < 0.0 {
+            Direction::Reverse
+        } else {
+            Direction::Normal
+        };
+        self.signal_motion(true)?;
+        if !self.try_step(self.phase_dir(dir))? {
+            trace!("velocity mode stopped at the limit {}", self.position);
+            self.velocity = None;
+            self.finish_move(true)?;
+            return Ok(false);
+        }
+        Ok(true)
+    }
+
+    /// Start a move of `steps` steps in the configured direction for schedulers like the
+    /// software tasks of RTIC. Does the first step right away and calls `schedule` with
+    /// `delay_us` if another step is due, then [`scheduled_wake`](Self::scheduled_wake) has to
+    /// be called from the scheduled task. Returns whether the move is still in progress.
+    pub fn step_for_scheduled(
+        &mut self,
+        steps: u32,
+        delay_us: u32,
+        schedule: impl FnMut(u32),
+    ) -> Result<bool, StepError> {
+        let half_steps = steps as i64 * self.drive_mode.half_steps() as i64;
+        self.start_motion(offset(self.position, self.dir, half_steps), delay_us);
+        self.scheduled_wake(schedule)
+    }
+
+    /// Do the next step of a move started with [`step_for_scheduled`](Self::step_for_scheduled)
+    /// and call `schedule` with the delay in microseconds until the following one, if there is
+    /// one. Returns whether the move is still in progress.
+    pub fn scheduled_wake(&mut self, mut schedule: impl FnMut(u32)) -> Result<bool, StepError> {
+        let Some(motion) = self.motion.as_ref() else {
+            return Ok(false);
+        };
+        let (target, interval_us) = (motion.target, motion.interval_us);
+        let moving = self.advance_motion(target)?;
+        if moving {
+            schedule(interval_us);
+        }
+        Ok(moving)
+    }
+
+    /// does one step of a non-blocking move towards `target` and ends it once reached
+    fn advance_motion(&mut self, target: i64) -> Result<bool, StepError> {
+        let moved = self.position != target;
+        if moved {
+            let dir = if target < self.position {
+                Direction::Reverse
+            } else {
+                Direction::Normal
+            };
+            if let Err(e) = self
+                .signal_motion(true)
+                .and_then(|_| self.step_towards(target, dir))
+            {
+                trace!("move failed at {}: {:?}", self.position, e);
+                self.motion = None;
+                // keep `e` as the last error even if the flag can't be lowered
+                let _ = self.write_motion_flag(false);
+                return Err(e);
+            }
+        }
+        if self.position == target {
+            trace!("move finished at {}", target);
+            self.motion = None;
+            self.finish_move(moved)?;
+        }
+        Ok(self.motion.is_some())
+    }
+
+    /// Hold the current phase with less heat: while no move is in progress [`poll`](Self::poll)
+    /// switches the coils on for `on_us` and off for `off_us` microseconds. The average current
+    /// drops with the duty cycle, but so does the holding torque, and the rotor can slip while
+    /// the coils are off if the load pulls on it. Set `on_us` to 0 to go back to a constant hold.
+    /// [`stop`](StepperMotor::stop) and [`power_off`](StepperMotor::power_off) also end it.
+    pub fn hold_chopped(&mut self, on_us: u32, off_us: u32) -> Result<(), StepError> {
+        self.chop = (on_us > 0).then_some(Chop {
+            on_us,
+            off_us,
+            on: false,
+            next_us: None,
+        });
+        if self.chop.is_none() {
+            self.apply_state()?;
+        }
+        Ok(())
+    }
+
+    /// Optional hold for vibrating mounts: while no move is in progress [`poll`](Self::poll) steps
+    /// back and forth by up to `amplitude_steps` steps around the current position, one step
+    /// every `delay_us` microseconds. The steps are counted, so [`position`](Self::position)
+    /// swings around the held position. Set `amplitude_steps` to 0 to return to the held position
+    /// and stop. Starting a move, [`stop`](StepperMotor::stop) and
+    /// [`power_off`](StepperMotor::power_off) end it right away.
+    pub fn dither_hold(&mut self, amplitude_steps: u8, delay_us: u32) {
+        let amplitude = amplitude_steps as i64 * self.drive_mode.half_steps() as i64;
+        match self.dither.as_mut() {
+            Some(dither) => {
+                dither.amplitude = amplitude;
+                dither.interval_us = delay_us;
+            }
+            None if amplitude > 0 => {
+                self.dither = Some(Dither {
+                    center: self.position,
+                    amplitude,
+                    interval_us: delay_us,
+                    next_us: None,
+                    dir: self.dir,
+                })
+            }
+            None => {}
+        }
+    }
+
+    /// does the next step of a dithering hold if it is due
+    fn poll_dither(&mut self, now_us: u64) -> Result<(), StepError> {
+        let Some(dither) = self.dither.as_mut() else {
+            return Ok(());
+        };
+        if dither.next_us.is_some_and(|next| now_us < next) {
+            return Ok(());
+        }
+        dither.next_us = Some(now_us.saturating_add(dither.interval_us as u64));
+        if dither.amplitude == 0 {
+            let center = dither.center;
+            if self.position == center {
+                self.dither = None;
+                return Ok(());
+            }
+            let dir = if center < self.position {
+                Direction::Reverse
+            } else {
+                Direction::Normal
+            };
+            return self.step_towards(center, dir);
+        }
+        let edge = offset(dither.center, dither.dir, dither.amplitude);
+        let reached = match dither.dir {
+            Direction::Normal => self.position >= edge,
+            Direction::Reverse => self.position <= edge,
+        };
+        if reached {
+            dither.dir = dither.dir.reversed();
+        }
+        let dir = dither.dir;
+        let edge = offset(dither.center, dir, dither.amplitude);
+        self.step_towards(edge, dir)
+    }
+
+    /// switches the coils of a chopped hold if it is due
+    fn poll_chop(&mut self, now_us: u64) -> Result<(), StepError> {
+        let Some(chop) = self.chop.as_mut() else {
+            return Ok(());
+        };
+        if chop.next_us.is_some_and(|next| now_us < next) {
+            return Ok(());
+        }
+        chop.on = !chop.on;
+        let us = if chop.on { chop.on_us } else { chop.off_us };
+        chop.next_us = Some(now_us.saturating_add(us as u64));
+        let pins = if chop.on {
+            self.coil_states()
+        } else {
+            [Low; 4]
+        };
+        self.write_pins(pins)
+    }
+
+    /// Cancel a non-blocking move, the velocity mode or homing started with
+    /// [`start_home`](Self::start_home), the motor stays at its current position
+    pub fn cancel_move(&mut self) {
+        self.motion = None;
+        self.velocity = None;
+        self.homing = None;
+        self.flag_pending = false;
+        let _ = self.signal_motion(false);
+    }
+
+    /// Whether a non-blocking move or the velocity mode is in progress
+    pub fn is_moving(&self) -> bool {
+        self.motion.is_some() || self.velocity.is_some()
+    }
+
+    /// Whether the motor is at the target of the last non-blocking move, unlike
+    /// `!is_moving()` this stays false after [`cancel_move`](Self::cancel_move) or a failed
+    /// step. True if no move was started yet, always false while the velocity mode runs.
+    pub fn at_target(&self) -> bool {
+        match self.target {
+            _ if self.velocity.is_some() => false,
+            Some(target) => target == self.position,
+            None => true,
+        }
+    }
+
+    /// Half steps left until a non-blocking move reaches its target, 0 if there is none
+    pub fn steps_remaining(&self) -> u64 {
+        self.motion
+            .as_ref()
+            .map_or(0, |m| m.target.abs_diff(self.position))
+    }
+
+    /// Rough estimate of the milliseconds left until a non-blocking move reaches its target,
+    /// 0 if there is none. Assumes [`poll`](Self::poll) is called often enough to keep up
+    /// with the step interval. Saturates at `u32::MAX`.
+    pub fn estimated_remaining_ms(&self) -> u32 {
+        let Some(motion) = self.motion.as_ref() else {
+            return 0;
+        };
+        let steps = self
+            .steps_remaining()
+            .div_ceil(self.drive_mode.half_steps() as u64);
+        let ms = steps.saturating_mul(motion.interval_us as u64) / 1000;
+        ms.min(u32::MAX as u64) as u32
+    }
+
+    /// Restrict the position to `min..=max`. Targets of [`step_to`](Self::step_to) and
+    /// [`start_move_to`](Self::start_move_to) are clamped into the range, other blocking moves
+    /// stop at the limit with [`MoveResult::stopped_early`] set and single steps that would
+    /// leave it fail with [`StepError::OutOfLimits`]. Homing and
+    /// [`step_until_pin`](Self::step_until_pin) ignore the limits.
+    pub fn set_limits(&mut self, min: i64, max: i64) -> &mut Self {
+        self.limits = Some((min.min(max), min.max(max)));
+        self
+    }
+
+    /// The soft limits set with [`set_limits`](Self::set_limits) as `(min, max)`,
+    /// `None` if the position is not limited
+    pub fn limits(&self) -> Option<(i64, i64)> {
+        self.limits
+    }
+
+    /// Remove the soft limits set with [`set_limits`](Self::set_limits)
+    pub fn clear_limits(&mut self) -> &mut Self {
+        self.limits = None;
+        self
+    }
+
+    /// Start homing in `dir` without blocking. Call [`poll_home`](Self::poll_home) regularly
+    /// afterwards, which does one step per call until the limit switch is reached.
+    pub fn start_home(&mut self, dir: Direction, max_steps: u32) {
+        self.homing = Some(Homing {
+            dir: self.phase_dir(dir),
+            steps_left: max_steps,
+        });
+    }
+
+    /// Continue homing started with [`start_home`](Self::start_home).
+    /// `limit_active` is the current state of the limit switch. Once it is active the position
+    /// is reset to zero and `Found` is returned. `Failed` is returned if the switch wasn't reached
+    /// within the maximum steps or no homing was started.
+    pub fn poll_home(&mut self, limit_active: bool) -> Result<HomeStatus, StepError> {
+        let Some(homing) = self.homing.as_mut() else {
+            return Ok(HomeStatus::Failed);
+        };
+        if limit_active {
+            self.homing = None;
+            self.zero_position();
+            self.signal_motion(false)?;
+            return Ok(HomeStatus::Found);
+        }
+        if homing.steps_left == 0 {
+            self.homing = None;
+            self.signal_motion(false)?;
+            return Ok(HomeStatus::Failed);
+        }
+        homing.steps_left -= 1;
+        let dir = homing.dir;
+        if let Err(e) = self
+            .signal_motion(true)
+            .and_then(|_| self.step_unlimited(dir))
+        {
+            self.homing = None;
+            // keep `e` as the last error even if the flag can't be lowered
+            let _ = self.write_motion_flag(false);
+            return Err(e);
+        }
+        Ok(HomeStatus::InProgress)
+    }
+
+    /// steps in `dir` towards `target`, using a single half step if a full step would overshoot
+    fn step_towards(&mut self, target: i64, dir: Direction) -> Result<(), StepError> {
+        let (_, half_steps) = self.next_phase(dir);
+        if half_steps as u64 > self.position.abs_diff(target) {
+            self.half_step_in(dir)
+        } else {
+            self.step_in(dir)
+        }
+    }
+
+    fn wait_us(&mut self, us: u32) -> Result<(), StepError> {
+        let max_us = self.max_single_delay_ms.saturating_mul(1000);
+        self.wait_parts(us, max_us, D::try_delay_us)
+    }
+
+    fn has_delay(&self) -> bool {
+        D::AVAILABLE && self.delay.is_some()
+    }
+
+    fn step_in(&mut self, dir: Direction) -> Result<(), StepError> {
+        let limits = self.check_limits(dir);
+        self.record(limits)?;
+        self.step_unlimited(dir)
+    }
+
+    /// remembers the error of `result` for `take_last_error`
+    fn record<T>(&mut self, result: Result<T, StepError>) -> Result<T, StepError> {
+        if let Err(e) = &result {
+            trace!("error at {}: {:?}", self.position, e);
+            self.last_error = Some(*e);
+            // a failed blocking move ends here, a non-blocking one lowers the flag itself
+            if !self.is_moving() {
+                self.flag_pending = false;
+                let _ = self.write_motion_flag(false);
+            }
+        }
+        result
+    }
+
+    /// fails if the next step in `dir` would leave the soft limits
+    fn check_limits(&self, dir: Direction) -> Result<(), StepError> {
+        if let Some((min, max)) = self.limits {
+            let next = offset(self.position, dir, self.next_phase(dir).1 as i64);
+            if next < min || next > max {
+                return Err(StepError::OutOfLimits);
+            }
+        }
+        Ok(())
+    }
+
+    /// a single half step in `dir` whatever the drive mode, respecting the soft limits
+    fn half_step_in(&mut self, dir: Direction) -> Result<(), StepError> {
+        let next = offset(self.position, dir, 1);
+        if self
+            .limits
+            .is_some_and(|(min, max)| next < min || next > max)
+        {
+            return self.record(Err(StepError::OutOfLimits));
+        }
+        self.enter(half_step(self.state, self.phase_dir(dir)), dir)?;
+        self.count(dir, 1);
+        Ok(())
+    }
+
+    /// like `step_in` but ignores the soft limits, for homing against switches
+    fn step_unlimited(&mut self, dir: Direction) -> Result<(), StepError> {
+        let half_steps = self.advance(dir)?;
+        self.count(dir, half_steps);
+        Ok(())
+    }
+
+    /// turns a target given in the position mode into an unwrapped position
+    fn resolve(&self, target: i64) -> i64 {
+        match self.position_mode {
+            PositionMode::Linear => target,
+            PositionMode::Rotary => self.position.saturating_add(self.nearest_turn(target)),
+        }
+    }
+
+    /// shortest signed distance to `target` modulo a revolution, at most half a revolution
+    fn nearest_turn(&self, target: i64) -> i64 {
+        let spr = self.steps_per_rev.max(1) as i64;
+        let mut delta = (target.rem_euclid(spr) - self.position.rem_euclid(spr)).rem_euclid(spr);
+        if delta > spr / 2 {
+            delta -= spr;
+        }
+        delta
+    }
+
+    /// where a move to `target` has to overshoot to first to end in the approach direction
+    fn approach_from(&self, target: i64) -> Option<i64> {
+        let (dir, margin) = self.approach?;
+        let overshoot = match self.phase_dir(dir) {
+            Direction::Normal if self.position > target => target.saturating_sub(margin as i64),
+            Direction::Reverse if self.position < target => target.saturating_add(margin as i64),
+            _ => return None,
+        };
+        Some(self.limit(overshoot))
+    }
+
+    /// clamps a target position into the soft limits
+    fn limit(&self, target: i64) -> i64 {
+        match self.limits {
+            Some((min, max)) => target.clamp(min, max),
+            None => target,
+        }
+    }
+
+    fn count(&mut self, dir: Direction, half_steps: u8) {
+        self.position = offset(self.position, dir, half_steps as i64);
+        let reported = self.position();
+        if let Some(cb) = self.position_callback.as_mut() {
+            cb.notify(self.position, reported, cb.every);
+        }
+        if let Some(cb) = self.persist_callback.as_mut() {
+            cb.notify(self.position, reported, cb.every);
+        }
+    }
+
+    /// sets the position without moving, e.g. after homing
+    fn set_position(&mut self, pos: i64) {
+        self.position = pos;
+        self.last_move = None;
+        if let Some(cb) = self.position_callback.as_mut() {
+            cb.last = pos;
+        }
+        if let Some(cb) = self.persist_callback.as_mut() {
+            cb.last = pos;
+        }
+    }
+
+    /// resets the position to zero after homing and stores it with the persist callback
+    fn zero_position(&mut self) {
+        self.set_position(0);
+        let reported = self.position();
+        if let Some(cb) = self.persist_callback.as_mut() {
+            (cb.cb)(reported);
+        }
+    }
+
+    /// applies the idle behavior after a move, zero step moves only if configured
+    fn finish_move(&mut self, moved: bool) -> Result<(), StepError> {
+        self.flag_pending = false;
+        self.signal_motion(false)?;
+        let reported = self.position();
+        if let Some(cb) = self.persist_callback.as_mut() {
+            cb.notify(self.position, reported, 1);
+        }
+        if !moved && !self.idle_on_zero_moves {
+            return Ok(());
+        }
+        match self.idle {
+            IdleBehavior::Hold if moved => Ok(()),
+            IdleBehavior::Hold => self.energize(),
+            IdleBehavior::Release => StepperMotor::power_off(self),
+        }
+    }
+
+    /// the result of a finished blocking move, after applying the idle behavior
+    fn move_result(
+        &mut self,
+        completed_steps: u32,
+        stopped_early: bool,
+    ) -> Result<MoveResult, StepError> {
+        trace!(
+            "blocking move finished at {} after {} steps, stopped early: {}",
+            self.position,
+            completed_steps,
+            stopped_early
+        );
+        self.finish_move(completed_steps > 0)?;
+        self.last_move = Some((self.move_start, self.position));
+        Ok(MoveResult {
+            completed_steps,
+            final_position: self.position(),
+            stopped_early,
+        })
+    }
+
+    /// `step_in` for blocking moves, returns false instead of failing at the soft limits
+    fn try_step(&mut self, dir: Direction) -> Result<bool, StepError> {
+        if self.check_limits(dir).is_err() {
+            return Ok(false);
+        }
+        self.step_unlimited(dir)?;
+        Ok(true)
+    }
+
+    /// does up to `steps` steps in the configured direction, waiting `delay_ms(i)` after
+    /// step `i`, and stops early at the soft limits
+    fn step_n(
+        &mut self,
+        steps: u32,
+        mut delay_ms: impl FnMut(u32) -> u32,
+    ) -> Result<MoveResult, StepError> {
+        for i in 0..steps {
+            if !self.try_step(self.dir)? {
+                return self.move_result(i, true);
+            }
+            self.wait_after_step(self.dir, delay_ms(i))?;
+        }
+        self.move_result(steps, false)
+    }
+
+    /// turns a direction of the position counter into the direction the phases are stepped in
+    /// and back, they only differ if the counting is inverted
+    fn phase_dir(&self, dir: Direction) -> Direction {
+        if self.count_inverted {
+            dir.reversed()
+        } else {
+            dir
+        }
+    }
+
+    /// phase the next step in `dir` lands on and how many half steps away it is
+    fn next_phase(&self, dir: Direction) -> (State, u8) {
+        self.next_phase_from(self.state, dir)
+    }
+
+    fn next_phase_from(&self, mut state: State, dir: Direction) -> (State, u8) {
+        let mut half_steps = 0;
+        loop {
+            state = half_step(state, self.phase_dir(dir));
+            half_steps += 1;
+            if self.mode_uses(self.drive_mode, state) {
+                return (state, half_steps);
+            }
+        }
+    }
+
+    /// phase a straight move from `position` at phase `from` to `target` ends on
+    fn phase_at(&self, from: State, position: i64, target: i64) -> State {
+        let distance = position.abs_diff(target);
+        if distance == 0 {
+            return from;
+        }
+        let dir = if target < position {
+            Direction::Reverse
+        } else {
+            Direction::Normal
+        };
+        let (mut state, half_steps) = self.next_phase_from(from, dir);
+        if half_steps as u64 > distance {
+            state = half_step(from, self.phase_dir(dir));
+        }
+        // after the first step every half step moves one entry along the table, which repeats
+        // every 8 half steps
+        for _ in 0..distance.saturating_sub(half_steps as u64) % 8 {
+            state = half_step(state, self.phase_dir(dir));
+        }
+        state
+    }
+
+    /// does one step in the active drive mode and returns the number of half steps moved
+    fn advance(&mut self, dir: Direction) -> Result<u8, StepError> {
+        let (state, half_steps) = self.next_phase(dir);
+        self.enter(state, dir)?;
+        Ok(half_steps)
+    }
+
+    fn enter(&mut self, state: State, dir: Direction) -> Result<(), StepError> {
+        self.check_fault()?;
+        if self.flag_pending {
+            self.flag_pending = false;
+            self.signal_motion(true)?;
+        }
+        let old = self.state;
+        self.state = state;
+        self.last_dir = Some(dir);
+        // any step invalidates the undo, blocking moves remember themselves once done
+        self.last_move = None;
+        trace!(
+            "{:?} -> {:?} ({:?}): {:?}",
+            old,
+            self.state,
+            dir,
+            self.coil_states()
+        );
+        self.energize()
+    }
+
+    /// writes the current phase, with a soft start if enabled and the coils are off
+    fn energize(&mut self) -> Result<(), StepError> {
+        if self.soft_start && !self.is_energized() && self.has_delay() {
+            self.soft_energize(self.coil_states())?;
+        }
+        self.apply_state()
+    }
+
+    /// pulses `states` with a rising duty cycle to avoid the jerk of switching on at once
+    fn soft_energize(&mut self, states: [PinState; 4]) -> Result<(), StepError> {
+        const CYCLES: u32 = 8;
+        const PERIOD_US: u32 = 500;
+        for i in 1..CYCLES {
+            let on_us = PERIOD_US * i / CYCLES;
+            self.write_pins(states)?;
+            self.wait_us(on_us)?;
+            self.write_pins([Low; 4])?;
+            self.wait_us(PERIOD_US - on_us)?;
+        }
+        Ok(())
+    }
+
+    fn apply_state(&mut self) -> Result<(), StepError> {
+        self.write_pins(self.coil_states())
+    }
+
+    /// coils energized by the current phase, after the phase offset
+    fn coil_states(&self) -> [PinState; 4] {
+        get_pin_states(shift_state(self.state, self.phase_offset))
+    }
+
+    /// whether `mode` steps onto `state`, judged by the coils it energizes after the phase offset
+    fn mode_uses(&self, mode: DriveMode, state: State) -> bool {
+        mode.uses(shift_state(state, self.phase_offset))
+    }
+
+    /// writes the coil `states` to the pins in the configured pin order
+    fn write_pins(&mut self, states: [PinState; 4]) -> Result<(), StepError> {
+        self.write_raw(self.ordered(states))
+    }
+
+    /// the pin levels driving the coil `states` in the configured pin order
+    fn ordered(&self, states: [PinState; 4]) -> [PinState; 4] {
+        self.pin_order
+            .map(|c| states.get(c).copied().unwrap_or(Low))
+    }
+
+    fn write_raw(&mut self, states: [PinState; 4]) -> Result<(), StepError> {
+        let result = self.write_coils(states);
+        self.record(result)
+    }
+
+    fn write_coils(&mut self, states: [PinState; 4]) -> Result<(), StepError> {
+        if !self.enabled && states.contains(&High) {
+            set_state(&mut self.enable, High)?;
+            self.enabled = true;
+        }
+        set_state(&mut self.in1, states[0])?;
+        set_state(&mut self.in2, states[1])?;
+        set_state(&mut self.in3, states[2])?;
+        set_state(&mut self.in4, states[3])?;
+        self.group.set_all(states)?;
+        self.group.flush()?;
+        self.pins = states;
+        Ok(())
+    }
+}
+
+impl<P1, P2, P3, P4, D, E, G, M, F, C, S> ULN2003<P1, P2, P3, P4, D, E, G, M, F, C, S>
+where
+    P1: OutputPin,
+    P2: OutputPin,
+    P3: OutputPin,
+    P4: OutputPin,
+    D: DelayNs,
+    E: OutputPin,
+    G: PinGroup,
+    M: OutputPin,
+    F: InputPin,
+    C: FnMut(i64),
+    S: FnMut(i64),
+{
+    /// Measure the steps per revolution of your motor. Steps in the configured direction with
+    /// `delay_ms` between steps and calls `turned` after every step, which should return true once
+    /// a full revolution is completed (e.g. confirmed by a user or a sensor).
+    /// The counted steps are converted to half steps like
+    /// [`steps_per_revolution`](Self::steps_per_revolution), stored as the new steps per
+    /// revolution and returned, so the result doesn't depend on the drive mode. Returns `None` and keeps the old value if `turned` didn't return true within
+    /// `max_steps` steps.
+    pub fn calibrate_spr(
+        &mut self,
+        delay_ms: u32,
+        max_steps: u32,
+        mut turned: impl FnMut() -> bool,
+    ) -> Result<Option<u32>, StepError> {
+        self.begin_move(delay_ms, max_steps == 0)?;
+        for steps in 1..=max_steps {
+            self.step()?;
+            self.wait_after_step(self.dir, delay_ms)?;
+            if turned() {
+                let half_steps = steps.saturating_mul(self.drive_mode.half_steps());
+                self.steps_per_rev = half_steps;
+                self.finish_move(true)?;
+                return Ok(Some(half_steps));
+            }
+        }
+        self.finish_move(max_steps > 0)?;
+        Ok(None)
+    }
+
+    /// Do `steps` steps with the configured speed.
+    /// Fails if no speed was set with [`set_speed_rpm`](Self::set_speed_rpm) or
+    /// [`set_step_delay_ms`](Self::set_step_delay_ms).
+    pub fn run(&mut self, steps: i32) -> Result<MoveResult, StepError> {
+        let ms = self.step_delay_ms.ok_or(StepError::NoSpeed);
+        let ms = self.record(ms)?;
+        self.step_for(steps, ms)
+    }
+
+    /// Like [`step_for`](BlockingStepperMotor::step_for) but for long moves: always `steps` steps in the
+    /// direction set with [`set_direction`](Self::set_direction), up to `u32::MAX`.
+    pub fn step_for_u32(&mut self, steps: u32, delay_ms: u32) -> Result<MoveResult, StepError> {
+        self.begin_move(delay_ms, steps == 0)?;
+        self.step_n(steps, |_| delay_ms)
+    }
+
+    /// Do `steps` steps with `delay_ms` between them and power off the coils right after.
+    /// Useful for battery powered projects that can't afford the holding current.
+    pub fn step_then_release(
+        &mut self,
+        steps: i32,
+        delay_ms: u32,
+    ) -> Result<MoveResult, StepError> {
+        let result = self.step_for(steps, delay_ms)?;
+        self.power_off()?;
+        Ok(result)
+    }
+
+    /// Duty cycle routine for things like a sensor arm: do `steps` steps with `delay_ms` between
+    /// them, hold the position energized for `hold_ms` and power off the coils. The coils are
+    /// off at the end whatever the [idle behavior](Self::set_idle_behavior) is.
+    pub fn move_hold_release(
+        &mut self,
+        steps: i32,
+        delay_ms: u32,
+        hold_ms: u32,
+    ) -> Result<MoveResult, StepError> {
+        let result = self.step_for(steps, delay_ms)?;
+        self.energize()?;
+        self.wait_ms(hold_ms)?;
+        self.power_off()?;
+        Ok(result)
+    }
+
+    /// Briefly energize the current phase for `strength_us` microseconds and switch the pins back
+    /// to how they were, e.g. for a detent click on a haptic knob. The rotor is pulled towards the
+    /// phase and released again without moving the position or the phase. Only noticeable while
+    /// the coils are off, and does nothing before the first step as there is no phase yet.
+    pub fn detent_pulse(&mut self, strength_us: u32) -> Result<(), StepError> {
+        let previous = self.pins;
+        self.apply_state()?;
+        self.wait_us(strength_us)?;
+        self.write_raw(previous)
+    }
+
+    /// Step to the absolute position `target` with `delay_ms` between steps.
+    /// The configured direction is not changed. In [`PositionMode::Rotary`] the motor takes
+    /// the shorter way around.
+    /// A move to the current position returns right away without touching the pins, unless
+    /// [`set_idle_on_zero_moves`](Self::set_idle_on_zero_moves) is enabled.
+    pub fn step_to(&mut self, target: i64, delay_ms: u32) -> Result<MoveResult, StepError> {
+        self.step_to_with_creep(target, delay_ms, 0, delay_ms)
+    }
+
+    /// Move a linear stage to the absolute position `mm` in millimeters with `delay_ms` between
+    /// steps, rounded to the nearest step. See [`set_linear_ratio`](Self::set_linear_ratio).
+    pub fn step_to_mm(&mut self, mm: f32, delay_ms: u32) -> Result<MoveResult, StepError> {
+        let target = self.steps_for_revolutions(mm / self.mm_per_rev);
+        self.step_to(target, delay_ms)
+    }
+
+    /// Turn to the absolute shaft angle `degrees` the shortest way, with `delay_ms` between steps.
+    /// Returns the direction chosen, see [`plan_move_to_angle`](Self::plan_move_to_angle), and
+    /// the result of the move.
+    pub fn step_to_angle(
+        &mut self,
+        degrees: f32,
+        delay_ms: u32,
+    ) -> Result<(Direction, MoveResult), StepError> {
+        let (dir, steps) = self.plan_move_to_angle(degrees);
+        let target = offset(self.position, self.phase_dir(dir), steps);
+        Ok((dir, self.move_to(target, delay_ms, 0, delay_ms)?))
+    }
+
+    /// Move `n` full steps (2048 per revolution on the 28BYJ-48) in the configured direction,
+    /// whatever the active drive mode. In half step mode this does two steps per full step.
+    /// The position is still counted in half steps.
+    pub fn full_steps(&mut self, n: i32, delay_ms: u32) -> Result<MoveResult, StepError> {
+        let target = offset(self.position, self.dir, 2 * n.max(0) as i64);
+        self.move_to(target, delay_ms, 0, delay_ms)
+    }
+
+    /// Jog by `count` steps, negative counts move in reverse. Unlike
+    /// [`step_for`](BlockingStepperMotor::step_for) the configured direction is ignored and not changed.
+    pub fn jog_steps(&mut self, count: i32, delay_ms: u32) -> Result<MoveResult, StepError> {
+        let half_steps = count as i64 * self.drive_mode.half_steps() as i64;
+        let target = self.position.saturating_add(half_steps);
+        self.move_to(target, delay_ms, 0, delay_ms)
+    }
+
+    /// Turn by `mdeg` thousandths of a degree without any float math, negative angles turn in
+    /// reverse. Rounds to the nearest half step, halfway cases away from zero. The configured
+    /// direction is ignored and not changed.
+    pub fn rotate_millideg(&mut self, mdeg: i32, delay_ms: u32) -> Result<MoveResult, StepError> {
+        let half_steps = div_round(mdeg as i64 * self.steps_per_rev as i64, 360_000);
+        let target = self.position.saturating_add(half_steps);
+        self.move_to(target, delay_ms, 0, delay_ms)
+    }
+
+    /// Turn by exactly `numerator / denominator` revolutions with integer math, negative
+    /// fractions turn in reverse, e.g. `(1, 12)` for a clock hand once per hour. The fraction of
+    /// a half step that is left over is carried to the next call with the same `denominator`, so
+    /// repeated moves never drift. Changing the denominator drops it. The configured direction
+    /// is ignored and not changed.
+    pub fn rotate_fraction(
+        &mut self,
+        numerator: i32,
+        denominator: u32,
+        delay_ms: u32,
+    ) -> Result<MoveResult, StepError> {
+        let den = denominator.max(1);
+        let carry = if self.fraction.1 == den {
+            self.fraction.0
+        } else {
+            0
+        };
+        let total = numerator as i64 * self.steps_per_rev as i64 + carry;
+        self.fraction = (total.rem_euclid(den as i64), den);
+        let target = self.position.saturating_add(total.div_euclid(den as i64));
+        self.move_to(target, delay_ms, 0, delay_ms)
+    }
+
+    /// Like [`step_to`](Self::step_to), but the last `creep_steps` steps are done with the
+    /// slower `creep_delay_ms` to avoid overshooting with heavy loads.
+    /// The delay switches directly from `delay_ms` to `creep_delay_ms` without any ramp,
+    /// so choose a creep delay the motor can still follow from the cruising speed.
+    pub fn step_to_with_creep(
+        &mut self,
+        target: i64,
+        delay_ms: u32,
+        creep_steps: u32,
+        creep_delay_ms: u32,
+    ) -> Result<MoveResult, StepError> {
+        self.move_to(self.resolve(target), delay_ms, creep_steps, creep_delay_ms)
+    }
+
+    /// Track a moving setpoint, e.g. the sun: before every step `target` is asked for the current
+    /// absolute target and the motor does one step towards it, in whichever direction it is,
+    /// then waits `delay_ms`. At the target it just waits `delay_ms` and asks again. Runs until
+    /// `until` returns true, which is checked before every step. Targets are resolved like with
+    /// [`step_to`](Self::step_to), [`MoveResult::stopped_early`] tells if the last one was
+    /// clamped by the soft limits.
+    pub fn follow(
+        &mut self,
+        mut target: impl FnMut() -> i64,
+        delay_ms: u32,
+        until: impl Fn() -> bool,
+    ) -> Result<MoveResult, StepError> {
+        self.begin_move(delay_ms, false)?;
+        let mut completed = 0u32;
+        let mut clamped = false;
+        while !until() {
+            let requested = self.resolve(target());
+            let next = self.limit(requested);
+            clamped = next != requested;
+            if next == self.position {
+                self.wait_ms(delay_ms)?;
+                continue;
+            }
+            let dir = if next < self.position {
+                Direction::Reverse
+            } else {
+                Direction::Normal
+            };
+            self.step_towards(next, dir)?;
+            completed = completed.saturating_add(1);
+            self.wait_after_step(dir, delay_ms)?;
+        }
+        self.move_result(completed, clamped)
+    }
+
+    /// Move back to where the last blocking move like [`step_for`](BlockingStepperMotor::step_for) or
+    /// [`step_to`](Self::step_to) started, with `delay_ms` between steps. Only that one move is
+    /// remembered. Any step done between it and the undo invalidates it, including single,
+    /// uncounted and non-blocking steps, as does setting the position e.g. by homing. Returns
+    /// `None` without moving if there is nothing to undo. Undoing twice redoes the move.
+    pub fn undo_last_move(&mut self, delay_ms: u32) -> Result<Option<MoveResult>, StepError> {
+        match self.last_move {
+            Some((start, end)) if end == self.position => {
+                self.move_to(start, delay_ms, 0, delay_ms).map(Some)
+            }
+            _ => Ok(None),
+        }
+    }
+
+    /// blocking move to a target in unwrapped positions
+    fn move_to(
+        &mut self,
+        target: i64,
+        delay_ms: u32,
+        creep_steps: u32,
+        creep_delay_ms: u32,
+    ) -> Result<MoveResult, StepError> {
+        let limited = self.limit(target);
+        self.begin_move(delay_ms.min(creep_delay_ms), limited == self.position)?;
+        let mut completed = 0;
+        if let Some(overshoot) = self.approach_from(limited) {
+            completed = self.step_along(overshoot, delay_ms, 0, delay_ms)?;
+        }
+        let last = self.step_along(limited, delay_ms, creep_steps, creep_delay_ms)?;
+        self.move_result(completed.saturating_add(last), limited != target)
+    }
+
+    /// steps straight to `target` and returns the number of steps
+    fn step_along(
+        &mut self,
+        target: i64,
+        delay_ms: u32,
+        creep_steps: u32,
+        creep_delay_ms: u32,
+    ) -> Result<u32, StepError> {
+        let dir = if target < self.position {
+            Direction::Reverse
+        } else {
+            Direction::Normal
+        };
+        let mut completed = 0u32;
+        while self.position != target {
+            self.step_towards(target, dir)?;
+            completed = completed.saturating_add(1);
+            let remaining = self.position.abs_diff(target);
+            let ms = if remaining > 0 && remaining <= creep_steps as u64 {
+                creep_delay_ms
+            } else {
+                delay_ms
+            };
+            self.wait_after_step(dir, ms)?;
+        }
+        Ok(completed)
+    }
+
+    /// Do `steps` steps with a trapezoidal speed profile. The delay ramps linearly from
+    /// `start_delay_ms` down to `min_delay_ms` over the first `accel_steps` steps, stays at
+    /// `min_delay_ms` while cruising and ramps back up over the last `decel_steps` steps.
+    /// If the move is too short for both ramps the cruising speed is never reached.
+    /// The ramps are linear by default, see [`set_accel_profile`](Self::set_accel_profile).
+    pub fn step_for_ramped(
+        &mut self,
+        steps: i32,
+        start_delay_ms: u32,
+        min_delay_ms: u32,
+        accel_steps: u32,
+        decel_steps: u32,
+    ) -> Result<MoveResult, StepError> {
+        self.begin_move(min_delay_ms.min(start_delay_ms), steps <= 0)?;
+        let mut delays = self.compute_ramp_delays(
+            steps,
+            start_delay_ms,
+            min_delay_ms,
+            accel_steps,
+            decel_steps,
+        );
+        self.step_n(steps.max(0) as u32, |_| {
+            delays.next().unwrap_or(min_delay_ms)
+        })
+    }
+
+    /// Step in `dir` with `delay_ms` between steps until `pin` reads `target`, e.g. to home against
+    /// a limit switch or stop at an optical flag. The pin is checked before every step.
+    /// Returns the steps taken, or [`StepError::MaxSteps`] if `pin` didn't reach `target` within
+    /// `max_steps`. The position counts all steps actually taken.
+    pub fn step_until_pin(
+        &mut self,
+        dir: Direction,
+        pin: &mut impl InputPin,
+        target: PinState,
+        max_steps: u32,
+        delay_ms: u32,
+    ) -> Result<MoveResult, StepError> {
+        self.begin_move(delay_ms, max_steps == 0)?;
+        let dir = self.phase_dir(dir);
+        for steps in 0..max_steps {
+            let reached = pin_is(pin, target);
+            if self.record(reached)? {
+                return self.move_result(steps, false);
+            }
+            self.step_unlimited(dir)?;
+            self.wait_after_step(dir, delay_ms)?;
+        }
+        let reached = pin_is(pin, target);
+        let reached = self.record(reached)?;
+        let result = self.move_result(max_steps, false)?;
+        if reached {
+            Ok(result)
+        } else {
+            self.record(Err(StepError::MaxSteps))
+        }
+    }
+
+    /// Do one step per entry of `delays`, waiting the entry in ms after the step.
+    /// Allows arbitrary speed profiles computed ahead of time.
+    pub fn step_with_schedule(&mut self, delays: &[u32]) -> Result<MoveResult, StepError> {
+        self.begin_move(
+            delays.iter().copied().min().unwrap_or(u32::MAX),
+            delays.is_empty(),
+        )?;
+        let mut schedule = delays.iter().copied();
+        self.step_n(delays.len() as u32, |_| schedule.next().unwrap_or(0))
+    }
+
+    /// Like [`step_with_schedule`](Self::step_with_schedule), but with delays in microseconds
+    pub fn step_with_schedule_us(&mut self, delays: &[u32]) -> Result<MoveResult, StepError> {
+        self.begin_move(
+            delays.iter().copied().min().unwrap_or(u32::MAX) / 1000,
+            delays.is_empty(),
+        )?;
+        let mut completed = 0u32;
+        for &us in delays {
+            if !self.try_step(self.dir)? {
+                return self.move_result(completed, true);
+            }
+            self.wait_us(self.scale_delay(self.dir, us))?;
+            completed = completed.saturating_add(1);
+        }
+        self.move_result(completed, false)
+    }
+
+    /// Sweep back and forth: `steps_each_way` steps in the configured direction, then the same
+    /// amount back, repeated `cycles` times with `delay_ms` between steps.
+    /// Ends at the starting position and leaves the configured direction unchanged.
+    pub fn oscillate(
+        &mut self,
+        steps_each_way: i32,
+        cycles: u32,
+        delay_ms: u32,
+    ) -> Result<MoveResult, StepError> {
+        self.begin_move(delay_ms, steps_each_way <= 0 || cycles == 0)?;
+        let start = self.position;
+        let mut completed = 0u32;
+        let mut stopped_early = false;
+        for _ in 0..cycles {
+            for _ in 0..steps_each_way {
+                if !self.try_step(self.dir)? {
+                    stopped_early = true;
+                    break;
+                }
+                self.wait_after_step(self.dir, delay_ms)?;
+                completed = completed.saturating_add(1);
+            }
+            while self.position != start {
+                self.step_towards(start, self.dir.reversed())?;
+                self.wait_after_step(self.dir.reversed(), delay_ms)?;
+                completed = completed.saturating_add(1);
+            }
+        }
+        self.move_result(completed, stopped_early)
+    }
+
+    /// Find the shortest delay the motor can follow under its current load. Starting at
+    /// `start_delay_ms`, does 64 steps at each delay and shortens it by `step_delay_decrement`
+    /// until `min_delay_ms` (or the configured [`min_delay_ms`](Self::min_delay_ms)) is reached
+    /// or the motor falls behind. `sense` has to return the measured position in half steps,
+    /// e.g. from an encoder, and may be off by up to one step. Returns the last delay the motor
+    /// followed, or [`StepError::Stalled`] if it already failed at `start_delay_ms`.
+    pub fn find_max_speed(
+        &mut self,
+        mut sense: impl FnMut() -> i64,
+        start_delay_ms: u32,
+        min_delay_ms: u32,
+        step_delay_decrement: u32,
+    ) -> Result<u32, StepError> {
+        let floor = min_delay_ms.max(self.min_delay_ms);
+        let mut delay_ms = start_delay_ms;
+        let mut fastest = None;
+        loop {
+            let sensed_start = sense();
+            let start = self.position;
+            BlockingStepperMotor::step_for(self, 64, delay_ms)?;
+            let sensed = sense().saturating_sub(sensed_start);
+            let moved = self.position.saturating_sub(start);
+            if sensed.abs_diff(moved) > self.drive_mode.half_steps() as u64 {
+                break;
+            }
+            fastest = Some(delay_ms);
+            if delay_ms <= floor || step_delay_decrement == 0 {
+                break;
+            }
+            delay_ms = delay_ms.saturating_sub(step_delay_decrement).max(floor);
+        }
+        let fastest = fastest.ok_or(StepError::Stalled);
+        self.record(fastest)
+    }
+
+    /// Check the wiring against a position sensor like an encoder: does `test_steps` steps in the
+    /// configured direction with `delay_ms` between them and compares the direction the position
+    /// counter moved with the change of `sense`. Returns `false` if the sensor moved the other
+    /// way or not at all, which usually means swapped coil wires. The motor is not moved back.
+    pub fn verify_direction(
+        &mut self,
+        mut sense: impl FnMut() -> i32,
+        test_steps: i32,
+        delay_ms: u32,
+    ) -> Result<bool, StepError> {
+        let sensed_start = sense();
+        let start = self.position;
+        BlockingStepperMotor::step_for(self, test_steps, delay_ms)?;
+        let sensed = (sense() as i64 - sensed_start as i64).signum();
+        let moved = self.position.saturating_sub(start).signum();
+        Ok(moved != 0 && sensed == moved)
+    }
+
+    /// Do a list of `(direction, steps)` segments in order with `delay_ms` between steps, e.g. for
+    /// winding patterns. Before each segment that changes the direction (compared to the direction
+    /// the motor last stepped in by any move, or the configured direction if it never stepped) the
+    /// backlash set with
+    /// [`set_backlash_steps`](Self::set_backlash_steps) is taken up with uncounted steps.
+    /// Leaves the configured direction unchanged. The backlash steps are not counted in
+    /// [`MoveResult::completed_steps`].
+    pub fn step_segments(
+        &mut self,
+        segments: &[(Direction, i32)],
+        delay_ms: u32,
+    ) -> Result<MoveResult, StepError> {
+        self.begin_move(delay_ms, segments.iter().all(|&(_, steps)| steps <= 0))?;
+        let mut completed = 0u32;
+        for &(dir, steps) in segments {
+            let dir = self.phase_dir(dir);
+            if steps <= 0 {
+                continue;
+            }
+            if dir != self.last_dir.unwrap_or(self.dir) {
+                for _ in 0..self.backlash_steps {
+                    self.advance(dir)?;
+                    self.wait_after_step(dir, delay_ms)?;
+                }
+            }
+            for _ in 0..steps {
+                if !self.try_step(dir)? {
+                    return self.move_result(completed, true);
+                }
+                self.wait_after_step(dir, delay_ms)?;
+                completed = completed.saturating_add(1);
+            }
+        }
+        self.move_result(completed, false)
+    }
+
+    /// Break the static friction of a stuck gearbox before a real move, e.g. after sitting idle
+    /// in the cold: wiggles `wiggle_steps` steps to both sides of the current position three
+    /// times with `delay_ms` between steps and ends where it started.
+    pub fn warm_up(&mut self, wiggle_steps: u8, delay_ms: u32) -> Result<MoveResult, StepError> {
+        self.begin_move(delay_ms, wiggle_steps == 0)?;
+        let start = self.position;
+        let mut completed = 0u32;
+        let mut stopped_early = false;
+        for _ in 0..3 {
+            for dir in [self.dir, self.dir.reversed()] {
+                for _ in 0..wiggle_steps {
+                    if !self.try_step(dir)? {
+                        stopped_early = true;
+                        break;
+                    }
+                    self.wait_after_step(dir, delay_ms)?;
+                    completed = completed.saturating_add(1);
+                }
+                while self.position != start {
+                    self.step_towards(start, dir.reversed())?;
+                    self.wait_after_step(dir.reversed(), delay_ms)?;
+                    completed = completed.saturating_add(1);
+                }
+            }
+        }
+        self.move_result(completed, stopped_early)
+    }
+
+    /// Measure the full travel of a linear mechanism with endstops at both ends.
+    /// Homes in reverse direction until `min_pin` reads `active` and resets the position to zero,
+    /// then steps in normal direction until `max_pin` reads `active`. Each part may take at most
+    /// `max_steps` steps. Returns the travel in half steps and sets it as soft limits if
+    /// `set_limits` is true.
+    pub fn measure_travel(
+        &mut self,
+        min_pin: &mut impl InputPin,
+        max_pin: &mut impl InputPin,
+        active: PinState,
+        delay_ms: u32,
+        max_steps: u32,
+        set_limits: bool,
+    ) -> Result<u32, StepError> {
+        let down = self.phase_dir(Direction::Reverse);
+        self.step_until_pin(down, min_pin, active, max_steps, delay_ms)?;
+        self.zero_position();
+        self.step_until_pin(down.reversed(), max_pin, active, max_steps, delay_ms)?;
+        let travel = self.position as u32;
+        if set_limits {
+            self.set_limits(0, self.position);
+        }
+        Ok(travel)
+    }
+}
+
+impl<P1, P2, P3, P4, D, E, G, M, F, C, S> ULN2003<P1, P2, P3, P4, D, E, G, M, F, C, S>
+where
+    P1: StatefulOutputPin,
+    P2: StatefulOutputPin,
+    P3: StatefulOutputPin,
+    P4: StatefulOutputPin,
+    D: MaybeDelay,
+    E: OutputPin,
+    G: PinGroup,
+    M: OutputPin,
+    F: InputPin,
+    C: FnMut(i64),
+    S: FnMut(i64),
+{
+    /// Read back the states of `in1` to `in4` as reported by the hardware, to check that they
+    /// match [`current_pin_states`](Self::current_pin_states), e.g. to find stuck pins.
+    /// Needs `&mut self` because [`StatefulOutputPin`] does.
+    pub fn read_pin_states(&mut self) -> Result<[PinState; 4], StepError> {
+        Ok([
+            read_state(&mut self.in1)?,
+            read_state(&mut self.in2)?,
+            read_state(&mut self.in3)?,
+            read_state(&mut self.in4)?,
+        ])
+    }
+}
+
+/// Logical state of a motor returned by [`ULN2003::state_snapshot`]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MotionState {
+    /// Position in half steps
+    pub position: i64,
+    /// Index of the current phase, 0 is all coils off and 1 to 8 the half step phases
+    pub phase: u8,
+    /// Stepping direction
+    pub direction: Direction,
+    /// Drive mode
+    pub drive_mode: DriveMode,
+    /// Delay between steps used by [`ULN2003::run`], if configured
+    pub step_delay_ms: Option<u32>,
+}
+
+/// Progress of a blocking move like [`step_for`](BlockingStepperMotor::step_for) or
+/// [`ULN2003::step_to`], also returned by [`MultiStepper`] and [`LinearMove`] for every motor.
+/// Calibration routines like [`ULN2003::calibrate_spr`], [`ULN2003::find_max_speed`],
+/// [`ULN2003::verify_direction`] and [`ULN2003::measure_travel`] return what they measured
+/// instead.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "trace-defmt", derive(defmt::Format))]
+pub struct MoveResult {
+    /// Steps done in the active drive mode, including a final half step to reach a target
+    pub completed_steps: u32,
+    /// Position after the move, same as [`ULN2003::position`]
+    pub final_position: i64,
+    /// Whether the soft limits stopped the move before all steps were done or clamped its target
+    pub stopped_early: bool,
+}
+
+/// the extra pins and callbacks moved by `ULN2003::convert`
+type Extras<E, M, F, C, S> = (
+    E,
+    M,
+    F,
+    Option<PositionCallback<C>>,
+    Option<PositionCallback<S>>,
+);
+
+struct PositionCallback<C> {
+    every: u32,
+    cb: C,
+    last: i64,
+}
+
+impl<C: FnMut(i64)> PositionCallback<C> {
+    /// calls the callback with `reported` if `position` moved by `min` half steps since the last call
+    fn notify(&mut self, position: i64, reported: i64, min: u32) {
+        if position.abs_diff(self.last) >= min as u64 {
+            self.last = position;
+            (self.cb)(reported);
+        }
+    }
+}
+
+struct Motion {
+    target: i64,
+    interval_us: u32,
+    next_us: Option<u64>,
+}
+
+struct Velocity {
+    target: f32,
+    current: f32,
+    last_us: Option<u64>,
+    last_step_us: Option<u64>,
+}
+
+struct Chop {
+    on_us: u32,
+    off_us: u32,
+    on: bool,
+    next_us: Option<u64>,
+}
+
+struct Dither {
+    center: i64,
+    amplitude: i64,
+    interval_us: u32,
+    next_us: Option<u64>,
+    dir: Direction,
+}
+
+struct Homing {
+    dir: Direction,
+    steps_left: u32,
+}
+
+/// Coils energized while the motor rests, see [`ULN2003::energization_state`]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "trace-defmt", derive(defmt::Format))]
+pub enum EnergizationState {
+    /// All coils off, no holding torque and no current, e.g. after
+    /// [`power_off`](StepperMotor::power_off) or [`stop`](StepperMotor::stop)
+    Released,
+    /// One coil holds, like the phases of [`DriveMode::Wave`]
+    SingleCoil,
+    /// Two coils hold with about twice the torque and current, like after
+    /// [`hold_strong`](ULN2003::hold_strong)
+    TwoCoils,
+    /// More coils are driven with [`set_coil`](ULN2003::set_coil) than any phase uses
+    Custom,
+}
+
+/// Result of [`ULN2003::poll_home`]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum HomeStatus {
+    /// The limit switch wasn't reached yet, keep polling
+    InProgress,
+    /// The limit switch was reached and the position reset to zero
+    Found,
+    /// The limit switch wasn't reached within the maximum steps
+    Failed,
+}
+
+/// gets returned if en Error happens while stepping
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "trace-defmt", derive(defmt::Format))]
+pub enum StepError {
+    /// Setting or reading one of the pins failed
+    Pin,
+    /// A delay is needed for this operation but none was given to [`ULN2003::new`]
+    MissingDelay,
+    /// No speed was configured for [`ULN2003::run`]
+    NoSpeed,
+    /// The requested delay is shorter than the configured [`ULN2003::min_delay_ms`],
+    /// so the motor would likely skip steps
+    TooFast,
+    /// The maximum number of steps was reached before the expected condition was met
+    MaxSteps,
+    /// The step would leave the soft limits set with [`ULN2003::set_limits`]
+    OutOfLimits,
+    /// The pin order given to [`ULN2003::set_pin_order`] is not a permutation of `0..4`
+    InvalidPinOrder,
+    /// The position sensor didn't follow the steps, the motor likely stalled or skipped steps
+    Stalled,
+    /// The coil index given to [`ULN2003::set_coil`] is not 0 to 3
+    InvalidCoil,
+    /// The ratio given to [`ULN2003::set_linear_ratio`] is zero, infinite or NaN
+    InvalidRatio,
+    /// A step or blocking move was started while a non-blocking move is in progress, see
+    /// [`ULN2003::is_moving`]. Finish it with [`ULN2003::poll`] or end it with
+    /// [`ULN2003::cancel_move`] first.
+    MoveInProgress,
+    /// The fault pin given to [`ULN2003::with_fault_pin`] is active, the driver board reported
+    /// e.g. an overcurrent
+    Fault,
+}
+
+impl<P1, P2, P3, P4, D, E, G, M, F, C, S> BlockingStepperMotor
+    for ULN2003<P1, P2, P3, P4, D, E, G, M, F, C, S>
+where
+    P1: OutputPin,
+    P2: OutputPin,
+    P3: OutputPin,
+    P4: OutputPin,
+    D: DelayNs,
+    E: OutputPin,
+    G: PinGroup,
+    M: OutputPin,
+    F: InputPin,
+    C: FnMut(i64),
+    S: FnMut(i64),
+{
+    fn step_for(&mut self, steps: i32, ms: u32) -> Result<MoveResult, StepError> {
+        self.begin_move(ms, steps <= 0)?;
+        self.step_n(steps.max(0) as u32, |_| ms)
+    }
+}
+
+impl<P1, P2, P3, P4, D, E, G, M, F, C, S> StepperMotor
+    for ULN2003<P1, P2, P3, P4, D, E, G, M, F, C, S>
+where
+    P1: OutputPin,
+    P2: OutputPin,
+    P3: OutputPin,
+    P4: OutputPin,
+    D: MaybeDelay,
+    E: OutputPin,
+    G: PinGroup,
+    M: OutputPin,
+    F: InputPin,
+    C: FnMut(i64),
+    S: FnMut(i64),
+{
+    fn step(&mut self) -> Result<(), StepError> {
+        if self.is_moving() {
+            return self.record(Err(StepError::MoveInProgress));
+        }
+        self.step_in(self.dir)
+    }
+
+    fn start_move(&mut self, steps: i32, delay_us: u32) {
+        ULN2003::start_move(self, steps, delay_us);
+    }
+
+    fn poll(&mut self, now_us: u64) -> Result<bool, StepError> {
+        ULN2003::poll(self, now_us)
+    }
+
+    fn set_direction(&mut self, dir: Direction) {
+        ULN2003::set_direction(self, dir);
+    }
+
+    fn stop(&mut self) -> Result<(), StepError> {
+        self.cancel_move();
+        self.realign()
+    }
+
+    fn power_off(&mut self) -> Result<(), StepError> {
+        self.cancel_move();
+        self.chop = None;
+        self.dither = None;
+        self.write_pins([Low; 4])?;
+        self.disable()
+    }
+
+    fn reset_phase(&mut self) -> Result<(), StepError> {
+        self.state = State::State0;
+        self.state = self.next_phase(self.phase_dir(Direction::Normal)).0;
+        self.apply_state()
+    }
+
+    fn step_delay_ms(&self) -> Option<u32> {
+        self.step_delay_ms
+    }
+
+    fn drive_mode(&self) -> DriveMode {
+        self.drive_mode
+    }
+
+    fn position(&self) -> i64 {
+        ULN2003::position(self)
+    }
+
+    fn motion_finished(&mut self, moved: bool) -> Result<(), StepError> {
+        self.finish_move(moved)
+    }
+
+    fn motion_started(&mut self) {
+        self.flag_pending = true;
+    }
+
+    fn max_rpm(&self) -> f32 {
+        delay_ms_to_rpm(self.min_delay_ms, self.steps_per_mode_rev())
+    }
+}
+
+/// Whether `order` is a permutation of `0..4` and can be used with [`ULN2003::set_pin_order`].
+/// Being a `const fn` it can check a fixed order at compile time:
+/// `const _: () = assert!(uln2003::is_valid_pin_order([0, 2, 1, 3]));`
+pub const fn is_valid_pin_order(order: [usize; 4]) -> bool {
+    let [a, b, c, d] = order;
+    a < 4 && b < 4 && c < 4 && d < 4 && a != b && a != c && a != d && b != c && b != d && c != d
+}
+
+/// Total time in ms a `step_for(steps, delay_ms)` call spends waiting.
+/// Saturates at `u32::MAX` (about 49 days) instead of wrapping around.
+pub fn move_duration_ms(steps: i32, delay_ms: u32) -> u32 {
+    (steps.max(0) as u32).saturating_mul(delay_ms)
+}
+
+/// delay after step `i` of a `steps` long move with acceleration and deceleration ramps
+fn ramp_delay_ms(
+    i: u32,
+    steps: u32,
+    start_delay_ms: u32,
+    min_delay_ms: u32,
+    accel_steps: u32,
+    decel_steps: u32,
+    profile: AccelProfile,
+) -> u32 {
+    let ramp = |n: u32, len: u32| {
+        if n >= len {
+            min_delay_ms
+        } else {
+            let diff = start_delay_ms.abs_diff(min_delay_ms) as u128;
+            let (n, len) = (n as u128, len as u128);
+            // the result is at most the difference, u128 fits len^3 without overflowing
+            let change = match profile {
+                AccelProfile::Linear => diff * n / len,
+                // smoothstep 3t^2 - 2t^3 with t = n / len
+                AccelProfile::SCurve => {
+                    diff * (3 * n * n * len - 2 * n * n * n) / (len * len * len)
+                }
+            };
+            if start_delay_ms >= min_delay_ms {
+                start_delay_ms - change as u32
+            } else {
+                start_delay_ms + change as u32
+            }
+        }
+    };
+    let remaining = steps - 1 - i;
+    // during overlapping ramps the slower one wins
+    ramp(i, accel_steps).max(ramp(remaining, decel_steps))
+}
+
+/// moves `pos` by `half_steps` in `dir`, saturating instead of overflowing
+fn offset(pos: i64, dir: Direction, half_steps: i64) -> i64 {
+    match dir {
+        Direction::Normal => pos.saturating_add(half_steps),
+        Direction::Reverse => pos.saturating_sub(half_steps),
+    }
+}
+
+/// Delay between steps in ms, rounded to the nearest ms, to turn at `rpm` revolutions per minute
+/// with `steps_per_rev` steps per revolution. Saturates at `u32::MAX` for speeds near zero.
+pub fn rpm_to_delay_ms(rpm: f32, steps_per_rev: u32) -> u32 {
+    round_u32(60_000.0 / (rpm * steps_per_rev as f32))
+}
+
+/// Speed in revolutions per minute when waiting `delay_ms` between steps
+/// with `steps_per_rev` steps per revolution
+pub fn delay_ms_to_rpm(delay_ms: u32, steps_per_rev: u32) -> f32 {
+    60_000.0 / (delay_ms as f32 * steps_per_rev as f32)
+}
+
+/// rounds half away from zero, `f32::round` is not available in `core`
+fn round(x: f32) -> i64 {
+    if x < 0.0 {
+        (x - 0.5) as i64
+    } else {
+        (x + 0.5) as i64
+    }
+}
+
+fn pin_is<P: InputPin>(pin: &mut P, state: PinState) -> Result<bool, StepError> {
+    let res = match state {
+        High => pin.is_high(),
+        Low => pin.is_low(),
+    };
+    res.map_err(|_| StepError::Pin)
+}
+
+/// integer division rounding to the nearest value, halfway cases away from zero, `d` > 0
+fn div_round(n: i64, d: i64) -> i64 {
+    if n < 0 {
+        n.saturating_sub(d / 2) / d
+    } else {
+        n.saturating_add(d / 2) / d
+    }
+}
+
+fn round_u32(x: f32) -> u32 {
+    round(x).clamp(0, u32::MAX as i64) as u32
+}
+
+fn read_state<P: StatefulOutputPin>(pin: &mut P) -> Result<PinState, StepError> {
+    match pin.is_set_high() {
+        Ok(high) => Ok(PinState::from(high)),
+        Err(_) => Err(StepError::Pin),
+    }
+}
+
+fn set_state<P: OutputPin>(pin: &mut P, state: PinState) -> Result<(), StepError> {
+    match pin.set_state(state) {
+        Ok(_) => Ok(()),
+        Err(_) => Err(StepError::Pin),
+    }
+}
+
+/// trait to prevent having to pass around the struct with all the generic arguments
+pub trait StepperMotor {
+    /// Do a single step. Fails with [`StepError::MoveInProgress`] while a non-blocking move is in
+    /// progress, as do the blocking moves.
+    fn step(&mut self) -> Result<(), StepError>;
+    /// Start a non-blocking move of `steps` steps with `delay_us` between steps, see
+    /// [`ULN2003::start_move`]. Together with [`poll`](Self::poll) this is the non-blocking
+    /// counterpart of [`step_for`](BlockingStepperMotor::step_for) for cooperative schedulers.
+    fn start_move(&mut self, steps: i32, delay_us: u32);
+    /// Drive a move started with [`start_move`](Self::start_move). `now_us` is the current time
+    /// in microseconds from any monotonic clock. Returns whether the move is still in progress.
+    fn poll(&mut self, now_us: u64) -> Result<bool, StepError>;
+    /// Set the stepping direction
+    fn set_direction(&mut self, dir: Direction);
+    /// Stoping cancels a non-blocking move started with [`start_move`](Self::start_move) (and the
+    /// velocity mode and homing of [`ULN2003`]), so [`poll`](Self::poll) doesn't step anymore,
+    /// sets all pins low and resets the phase to the all-off state, see [`ULN2003::realign`].
+    /// The next step energizes the first phase. The position is not changed.
+    fn stop(&mut self) -> Result<(), StepError>;
+    /// Cancels a non-blocking move like [`stop`](Self::stop) and sets all pins low but keeps the
+    /// current phase, so the next step continues from it without a glitch. [`ULN2003::wake`]
+    /// energizes the kept phase again.
+    /// Also powers down the driver board if it has an enable pin. The position is not changed.
+    fn power_off(&mut self) -> Result<(), StepError>;
+    /// Sets the phase to the first phase of the active drive mode and energizes it, so the motor
+    /// holds at a known phase. The rotor may snap to it, but the position is not changed.
+    fn reset_phase(&mut self) -> Result<(), StepError>;
+    /// Highest speed in revolutions per minute the motor can sustain with the configured minimum delay
+    fn max_rpm(&self) -> f32;
+    /// Configured delay between steps in ms, `None` if no speed was set
+    fn step_delay_ms(&self) -> Option<u32>;
+    /// The active drive mode, see [`ULN2003::set_drive_mode`]
+    fn drive_mode(&self) -> DriveMode;
+    /// Current position in half steps, see [`ULN2003::position`]
+    fn position(&self) -> i64;
+    /// Called by [`MultiStepper`] and [`LinearMove`] once they are done stepping this motor, so
+    /// it can apply its idle behavior like after its own blocking moves. `moved` is whether the
+    /// motor did any steps. Does nothing by default, see [`ULN2003::set_idle_behavior`].
+    fn motion_finished(&mut self, moved: bool) -> Result<(), StepError> {
+        let _ = moved;
+        Ok(())
+    }
+    /// Called by [`MultiStepper`] and [`LinearMove`] before they step this motor, so it can
+    /// raise its motion flag with the first step. Does nothing by default, see
+    /// [`ULN2003::with_motion_flag_pin`].
+    fn motion_started(&mut self) {}
+}
+
+/// The blocking moves of [`StepperMotor`], only implemented for motors with a [`DelayNs`] so
+/// they don't exist at all with [`NoDelay`]
+pub trait BlockingStepperMotor: StepperMotor {
+    /// Do multiple steps with a given delay in ms.
+    /// The delay is waited once per step and never multiplied, so any `u32` delay is safe.
+    /// Any `steps` up to `i32::MAX` is supported, zero or negative counts don't step at all and
+    /// leave the pins untouched.
+    /// Steps that would leave the soft limits end the move early instead of failing, see
+    /// [`MoveResult`].
+    fn step_for(&mut self, steps: i32, delay: u32) -> Result<MoveResult, StepError>;
+}
+
+/// Direction the motor turns in. Just reverses the order of the internal states.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "trace-defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Direction {
+    /// Default direction
+    #[default]
+    Normal,
+    /// Reversed direction
+    Reverse,
+}
+
+impl Direction {
+    /// The opposite direction
+    pub fn reversed(self) -> Self {
+        match self {
+            Direction::Normal => Direction::Reverse,
+            Direction::Reverse => Direction::Normal,
+        }
+    }
+}
+
+/// How the position counter behaves over multiple revolutions
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "trace-defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum PositionMode {
+    /// The position grows without bounds, e.g. for linear actuators
+    #[default]
+    Linear,
+    /// The position wraps at a full revolution, e.g. for endlessly rotating indexers.
+    /// Absolute moves take the shorter way around.
+    Rotary,
+}
+
+/// Shape of the speed ramps of a ramped move
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "trace-defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum AccelProfile {
+    /// The delay changes by the same amount every step. Simple, but jerky at the ramp corners.
+    #[default]
+    Linear,
+    /// The delay changes slowly at the start and end of a ramp and fastest in the middle,
+    /// so there are no sudden changes in acceleration. Smoother, e.g. for camera sliders.
+    SCurve,
+}
+
+/// What happens with the coils once a move is done.
+/// The default is [`Hold`](Self::Hold), or [`Release`](Self::Release) with the
+/// `release-on-idle` feature.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "trace-defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum IdleBehavior {
+    /// Keep the last phase energized to hold the position
+    Hold,
+    /// Switch the coils off like [`StepperMotor::power_off`] to save power and heat
+    Release,
+}
+
+/// the idle behavior of new motors, selected by the `release-on-idle` feature
+#[cfg(not(feature = "release-on-idle"))]
+const DEFAULT_IDLE: IdleBehavior = IdleBehavior::Hold;
+#[cfg(feature = "release-on-idle")]
+const DEFAULT_IDLE: IdleBehavior = IdleBehavior::Release;
+
+impl Default for IdleBehavior {
+    fn default() -> Self {
+        DEFAULT_IDLE
+    }
+}
+
+/// Presets for common motor variants, see [`ULN2003::apply_profile`]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "trace-defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum MotorProfile {
+    /// The common 5V 28BYJ-48, with a conservative minimum delay of 2 ms
+    Byj48_5V,
+    /// The 12V 28BYJ-48, which follows the default minimum delay of 1 ms
+    Byj48_12V,
+}
+
+impl MotorProfile {
+    /// Shortest delay between steps in ms the variant can usually follow
+    pub const fn min_delay_ms(self) -> u32 {
+        match self {
+            MotorProfile::Byj48_5V => 2,
+            MotorProfile::Byj48_12V => DEFAULT_MIN_DELAY_MS,
+        }
+    }
+
+    /// Half steps per output shaft revolution
+    pub const fn steps_per_revolution(self) -> u32 {
+        match self {
+            MotorProfile::Byj48_5V | MotorProfile::Byj48_12V => DEFAULT_STEPS_PER_REVOLUTION,
+        }
+    }
+}
+
+/// How the coils are driven while stepping
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "trace-defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum DriveMode {
+    /// Alternate between one and two energized coils, 8 states per cycle
+    #[default]
+    HalfStep,
+    /// Always two energized coils, 4 states per cycle. More torque but twice the current.
+    FullStep,
+    /// Always one energized coil, 4 states per cycle. Less torque and current.
+    Wave,
+}
+
+impl DriveMode {
+    /// half steps moved by a single step in this mode
+    fn half_steps(self) -> u32 {
+        match self {
+            DriveMode::HalfStep => 1,
+            DriveMode::FullStep | DriveMode::Wave => 2,
+        }
+    }
+
+    fn uses(self, s: State) -> bool {
+        match self {
+            DriveMode::HalfStep => !matches!(s, State::State0),
+            DriveMode::FullStep => matches!(
+                s,
+                State::State2 | State::State4 | State::State6 | State::State8
+            ),
+            DriveMode::Wave => matches!(
+                s,
+                State::State1 | State::State3 | State::State5 | State::State7
+            ),
+        }
+    }
 }
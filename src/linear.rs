@@ -0,0 +1,121 @@
+use embedded_hal::delay::DelayNs;
+
+use crate::{Direction, MoveResult, StepError, StepperMotor};
+
+/// Straight line move of two motors, e.g. the axes of a pen plotter. The steps are interleaved
+/// with Bresenham's algorithm so both axes arrive at the same time: the axis with more steps
+/// steps every time, the other one only as often as needed to stay on the line.
+pub struct LinearMove<'a, D: DelayNs> {
+    motors: [&'a mut dyn StepperMotor; 2],
+    delay: D,
+    steps: [u32; 2],
+    acc: [u32; 2],
+    major: u32,
+    done: u32,
+    next_us: Option<u64>,
+}
+
+impl<'a, D: DelayNs> LinearMove<'a, D> {
+    /// Plan a move of `dx` steps on `x` and `dy` steps on `y`, negative counts move in reverse.
+    /// Sets the direction of both motors, the steps are done by [`execute`](Self::execute) or
+    /// [`poll`](Self::poll).
+    pub fn new(
+        x: &'a mut dyn StepperMotor,
+        y: &'a mut dyn StepperMotor,
+        dx: i32,
+        dy: i32,
+        delay: D,
+    ) -> Self {
+        for (motor, d) in [(&mut *x, dx), (&mut *y, dy)] {
+            motor.set_direction(if d < 0 {
+                Direction::Reverse
+            } else {
+                Direction::Normal
+            });
+        }
+        let steps = [dx.unsigned_abs(), dy.unsigned_abs()];
+        let major = steps[0].max(steps[1]);
+        Self {
+            motors: [x, y],
+            delay,
+            steps,
+            acc: [major / 2; 2],
+            major,
+            done: 0,
+            next_us: None,
+        }
+    }
+
+    /// Do the whole move, waiting `delay_ms` after each step of the longer axis. Returns the
+    /// result of the move of both axes, counting the steps already done by [`poll`](Self::poll).
+    /// Both motors apply their idle behavior once the move is done, see
+    /// [`StepperMotor::motion_finished`].
+    pub fn execute(&mut self, delay_ms: u32) -> Result<[MoveResult; 2], StepError> {
+        while self.advance()? {
+            self.delay.delay_ms(delay_ms);
+        }
+        let mut results = self.steps.map(|completed_steps| MoveResult {
+            completed_steps,
+            final_position: 0,
+            stopped_early: false,
+        });
+        for (result, motor) in results.iter_mut().zip(self.motors.iter()) {
+            result.final_position = motor.position();
+        }
+        Ok(results)
+    }
+
+    /// Non-blocking version of [`execute`](Self::execute): does the next step once `interval_us`
+    /// passed since the last one. `now_us` is the current time in microseconds from any monotonic
+    /// clock. Returns whether the move is still in progress.
+    pub fn poll(&mut self, now_us: u64, interval_us: u32) -> Result<bool, StepError> {
+        let due = match self.next_us {
+            Some(next) => now_us >= next,
+            None => true,
+        };
+        if due {
+            self.next_us = Some(now_us.saturating_add(interval_us as u64));
+            self.advance()?;
+        }
+        Ok(!self.is_done())
+    }
+
+    /// Whether both axes reached their target
+    pub fn is_done(&self) -> bool {
+        self.done >= self.major
+    }
+
+    /// Give back the motors and the delay
+    pub fn release(self) -> ([&'a mut dyn StepperMotor; 2], D) {
+        (self.motors, self.delay)
+    }
+
+    /// one step of the longer axis and the matching steps of the other, false once done
+    fn advance(&mut self) -> Result<bool, StepError> {
+        if self.is_done() {
+            return Ok(false);
+        }
+        if self.done == 0 {
+            for (motor, &steps) in self.motors.iter_mut().zip(self.steps.iter()) {
+                if steps > 0 {
+                    motor.motion_started();
+                }
+            }
+        }
+        let axes = self.steps.iter().zip(self.acc.iter_mut());
+        for (motor, (&steps, acc)) in self.motors.iter_mut().zip(axes) {
+            *acc += steps;
+            if *acc >= self.major {
+                *acc -= self.major;
+                motor.step()?;
+            }
+        }
+        self.done += 1;
+        if self.is_done() {
+            for (motor, &steps) in self.motors.iter_mut().zip(self.steps.iter()) {
+                motor.motion_finished(steps > 0)?;
+            }
+        }
+        Ok(true)
+    }
+}
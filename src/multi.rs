@@ -0,0 +1,88 @@
+use embedded_hal::delay::DelayNs;
+
+use crate::{MoveResult, StepError, StepperMotor};
+
+/// Steps multiple motors using one shared delay.
+/// The motors themselves don't need a delay, each keeps its own phase and direction.
+pub struct MultiStepper<'a, D: DelayNs, const N: usize> {
+    motors: [&'a mut dyn StepperMotor; N],
+    delay: D,
+}
+
+impl<'a, D: DelayNs, const N: usize> MultiStepper<'a, D, N> {
+    /// Create a new MultiStepper from the motors and the delay shared between them
+    pub fn new(motors: [&'a mut dyn StepperMotor; N], delay: D) -> Self {
+        Self { motors, delay }
+    }
+
+    /// Do `steps` steps on all motors at once, waiting `delay_ms` after each step of all motors.
+    /// Returns the result of the move of every motor. Every motor applies its idle behavior
+    /// afterwards, see [`StepperMotor::motion_finished`].
+    pub fn step_for(&mut self, steps: i32, delay_ms: u32) -> Result<[MoveResult; N], StepError> {
+        self.start([steps.max(0) as u32; N]);
+        for _ in 0..steps {
+            for motor in self.motors.iter_mut() {
+                motor.step()?;
+            }
+            self.delay.delay_ms(delay_ms);
+        }
+        self.finish([steps.max(0) as u32; N])
+    }
+
+    /// Do a different number of steps on every motor, waiting `delay_ms` after each round.
+    /// Motors that are done stop stepping while the others continue.
+    pub fn step_each(
+        &mut self,
+        steps: [u32; N],
+        delay_ms: u32,
+    ) -> Result<[MoveResult; N], StepError> {
+        let max = steps.iter().copied().max().unwrap_or(0);
+        self.start(steps);
+        for i in 0..max {
+            for (motor, steps) in self.motors.iter_mut().zip(steps) {
+                if i < steps {
+                    motor.step()?;
+                }
+            }
+            self.delay.delay_ms(delay_ms);
+        }
+        self.finish(steps)
+    }
+
+    /// Give back the motors and the delay
+    pub fn release(self) -> ([&'a mut dyn StepperMotor; N], D) {
+        (self.motors, self.delay)
+    }
+
+    /// tells the motors with steps to do that a move starts
+    fn start(&mut self, steps: [u32; N]) {
+        for (motor, steps) in self.motors.iter_mut().zip(steps) {
+            if steps > 0 {
+                motor.motion_started();
+            }
+        }
+    }
+
+    /// applies the idle behavior of every motor after a move with `steps` steps and returns
+    /// its results
+    fn finish(&mut self, steps: [u32; N]) -> Result<[MoveResult; N], StepError> {
+        for (motor, steps) in self.motors.iter_mut().zip(steps) {
+            motor.motion_finished(steps > 0)?;
+        }
+        Ok(self.results(steps))
+    }
+
+    /// results of a move with `steps` steps on every motor, the soft limits fail the steps
+    /// instead of stopping early
+    fn results(&self, steps: [u32; N]) -> [MoveResult; N] {
+        let mut results = steps.map(|completed_steps| MoveResult {
+            completed_steps,
+            final_position: 0,
+            stopped_early: false,
+        });
+        for (result, motor) in results.iter_mut().zip(self.motors.iter()) {
+            result.final_position = motor.position();
+        }
+        results
+    }
+}
@@ -0,0 +1,508 @@
+#![allow(clippy::unwrap_used, clippy::indexing_slicing)]
+
+extern crate std;
+
+use std::cell::Cell;
+use std::rc::Rc;
+
+use super::*;
+
+/// output and input pin sharing its level with the clones kept by the test
+#[derive(Clone, Default)]
+struct Pin(Rc<Cell<bool>>);
+
+impl Pin {
+    fn high(&self) -> bool {
+        self.0.get()
+    }
+}
+
+impl ErrorType for Pin {
+    type Error = Infallible;
+}
+
+impl OutputPin for Pin {
+    fn set_low(&mut self) -> Result<(), Infallible> {
+        self.0.set(false);
+        Ok(())
+    }
+
+    fn set_high(&mut self) -> Result<(), Infallible> {
+        self.0.set(true);
+        Ok(())
+    }
+}
+
+impl InputPin for Pin {
+    fn is_high(&mut self) -> Result<bool, Infallible> {
+        Ok(self.0.get())
+    }
+
+    fn is_low(&mut self) -> Result<bool, Infallible> {
+        Ok(!self.0.get())
+    }
+}
+
+/// output pin that counts how often it was driven high
+#[derive(Clone, Default)]
+struct Flag {
+    level: Pin,
+    raised: Rc<Cell<u32>>,
+}
+
+impl ErrorType for Flag {
+    type Error = Infallible;
+}
+
+impl OutputPin for Flag {
+    fn set_low(&mut self) -> Result<(), Infallible> {
+        self.level.set_low()
+    }
+
+    fn set_high(&mut self) -> Result<(), Infallible> {
+        self.raised.set(self.raised.get() + 1);
+        self.level.set_high()
+    }
+}
+
+/// delay that only adds up the waited time in ns
+#[derive(Clone, Default)]
+struct Delay(Rc<Cell<u64>>);
+
+impl DelayNs for Delay {
+    fn delay_ns(&mut self, ns: u32) {
+        self.0.set(self.0.get() + ns as u64);
+    }
+}
+
+type Motor = ULN2003<Pin, Pin, Pin, Pin, Delay>;
+
+fn motor() -> (Motor, [Pin; 4]) {
+    let pins: [Pin; 4] = Default::default();
+    let [a, b, c, d] = pins.clone();
+    (ULN2003::new(a, b, c, d, Some(Delay::default())), pins)
+}
+
+fn energized(pins: &[Pin; 4]) -> bool {
+    pins.iter().any(Pin::high)
+}
+
+#[test]
+fn stop_and_power_off_cancel_moves() {
+    let (mut motor, pins) = motor();
+    let dm: &mut dyn StepperMotor = &mut motor;
+    dm.start_move(10, 10);
+    dm.poll(0).unwrap();
+    dm.poll(10).unwrap();
+    dm.stop().unwrap();
+    assert!(!dm.poll(20).unwrap());
+    assert_eq!(dm.position(), 2);
+
+    dm.start_move(10, 10);
+    dm.poll(30).unwrap();
+    dm.power_off().unwrap();
+    assert!(!dm.poll(40).unwrap());
+    assert!(!energized(&pins));
+    assert_eq!(dm.position(), 3);
+}
+
+#[test]
+fn speed_above_max_rpm_fails() {
+    for mode in [DriveMode::HalfStep, DriveMode::FullStep, DriveMode::Wave] {
+        let (mut motor, _) = motor();
+        motor.set_drive_mode(mode);
+        let max = StepperMotor::max_rpm(&motor);
+        assert!(motor.set_speed_rpm(max).is_ok());
+        for rpm in [max * 1.02, max * 1.4, max * 2.0] {
+            assert_eq!(motor.set_speed_rpm(rpm).err(), Some(StepError::TooFast));
+        }
+        motor.set_speed_rpm(max / 2.0).unwrap();
+        assert_eq!(motor.step_delay_ms(), Some(2));
+    }
+}
+
+#[test]
+fn calibrate_spr_counts_half_steps() {
+    for (mode, steps) in [
+        (DriveMode::HalfStep, 4096),
+        (DriveMode::FullStep, 2048),
+        (DriveMode::Wave, 2048),
+    ] {
+        let (mut motor, _) = motor();
+        motor.set_drive_mode(mode).set_steps_per_revolution(1000);
+        let mut count = 0;
+        let spr = motor
+            .calibrate_spr(1, 5000, || {
+                count += 1;
+                count == steps
+            })
+            .unwrap();
+        assert_eq!(spr, Some(4096));
+        assert_eq!(motor.steps_per_revolution(), 4096);
+        assert_eq!(motor.steps_for_angle(360.0), 4096);
+    }
+}
+
+#[test]
+fn zero_moves_skip_delay_validation() {
+    let (mut motor, pins) = motor();
+    let result = motor.step_to(0, 0).unwrap();
+    assert_eq!(result.completed_steps, 0);
+    assert_eq!(motor.step_for(0, 0).unwrap().completed_steps, 0);
+    assert!(!energized(&pins));
+    assert_eq!(motor.step_to(5, 0).err(), Some(StepError::TooFast));
+
+    let [a, b, c, d]: [Pin; 4] = Default::default();
+    let mut motor: Motor = ULN2003::new(a, b, c, d, None);
+    assert_eq!(motor.step_to(0, 1).unwrap().completed_steps, 0);
+    assert_eq!(motor.step_to(1, 1).err(), Some(StepError::MissingDelay));
+}
+
+#[test]
+fn coordinators_apply_idle_behavior() {
+    let (mut x, x_pins) = motor();
+    let (mut y, y_pins) = motor();
+    x.set_idle_behavior(IdleBehavior::Release);
+    y.set_idle_behavior(IdleBehavior::Release);
+    let mut multi = MultiStepper::new([&mut x, &mut y], Delay::default());
+    multi.step_for(3, 1).unwrap();
+    assert!(!energized(&x_pins) && !energized(&y_pins));
+    multi.step_each([2, 0], 1).unwrap();
+    assert!(!energized(&x_pins) && !energized(&y_pins));
+    drop(multi);
+    assert_eq!((x.position(), y.position()), (5, 3));
+
+    let mut line = LinearMove::new(&mut x, &mut y, 4, -2, Delay::default());
+    line.execute(1).unwrap();
+    assert!(!energized(&x_pins) && !energized(&y_pins));
+    drop(line);
+    assert_eq!((x.position(), y.position()), (9, 1));
+
+    x.set_idle_behavior(IdleBehavior::Hold);
+    MultiStepper::new([&mut x], Delay::default())
+        .step_for(1, 1)
+        .unwrap();
+    assert!(energized(&x_pins));
+}
+
+#[test]
+fn motion_flag_only_raised_by_steps() {
+    let flag = Flag::default();
+    let (mut motor, (mut other, _)) = (motor().0.with_motion_flag_pin(flag.clone()), motor());
+    motor.step_to(0, 1).unwrap();
+    motor.step_for(0, 1).unwrap();
+    motor.set_limits(0, 0);
+    motor.step_for(3, 1).unwrap();
+    assert_eq!(flag.raised.get(), 0);
+
+    motor.clear_limits();
+    motor.step_for(3, 1).unwrap();
+    assert_eq!(flag.raised.get(), 1);
+    assert!(!flag.level.high());
+
+    motor.start_home(Direction::Reverse, 5);
+    motor.poll_home(false).unwrap();
+    assert!(flag.level.high());
+    assert_eq!(motor.poll_home(true).unwrap(), HomeStatus::Found);
+    assert!(!flag.level.high());
+    assert_eq!(flag.raised.get(), 2);
+
+    let mut multi = MultiStepper::new([&mut motor, &mut other], Delay::default());
+    multi.step_each([0, 2], 1).unwrap();
+    assert_eq!(flag.raised.get(), 2);
+    multi.step_each([2, 0], 1).unwrap();
+    assert_eq!(flag.raised.get(), 3);
+    drop(multi);
+
+    let mut line = LinearMove::new(&mut motor, &mut other, 3, 1, Delay::default());
+    assert!(line.poll(0, 10).unwrap());
+    assert!(flag.level.high());
+    line.execute(1).unwrap();
+    assert!(!flag.level.high());
+    assert_eq!(flag.raised.get(), 4);
+}
+
+#[test]
+fn backlash_follows_last_motion() {
+    let delay = Delay::default();
+    let [a, b, c, d]: [Pin; 4] = Default::default();
+    let mut motor: Motor = ULN2003::new(a, b, c, d, Some(delay.clone()));
+    motor.set_backlash_steps(2);
+    let waited_ms = |motor: &mut Motor, segments: &[(Direction, i32)]| {
+        let before = delay.0.get();
+        motor.step_segments(segments, 1).unwrap();
+        (delay.0.get() - before) / 1_000_000
+    };
+    motor.step_for(3, 1).unwrap();
+    assert_eq!(waited_ms(&mut motor, &[(Direction::Normal, 2)]), 2);
+    motor.step_to(0, 1).unwrap();
+    assert_eq!(waited_ms(&mut motor, &[(Direction::Reverse, 1)]), 1);
+    assert_eq!(waited_ms(&mut motor, &[(Direction::Normal, 1)]), 3);
+    motor.step_uncounted(Direction::Reverse).unwrap();
+    assert_eq!(waited_ms(&mut motor, &[(Direction::Reverse, 1)]), 1);
+    assert_eq!(motor.position(), -1);
+}
+
+#[test]
+fn invalid_linear_ratio_is_rejected() {
+    let (mut motor, _) = motor();
+    motor.set_steps_per_revolution(100);
+    motor.set_linear_ratio(2.0).unwrap();
+    for ratio in [0.0, -0.0, f32::INFINITY, f32::NEG_INFINITY, f32::NAN] {
+        assert_eq!(
+            motor.set_linear_ratio(ratio).err(),
+            Some(StepError::InvalidRatio)
+        );
+    }
+    assert_eq!(motor.step_to_mm(1.0, 1).unwrap().final_position, 50);
+    assert_eq!(motor.current_mm(), 1.0);
+}
+
+#[test]
+fn not_at_target_in_velocity_mode() {
+    let (mut motor, _) = motor();
+    assert!(motor.at_target());
+    motor.set_velocity(100);
+    assert!(!motor.at_target());
+    motor.poll(0).unwrap();
+    assert!(!motor.at_target());
+    motor.cancel_move();
+    assert!(motor.at_target());
+    motor.start_move_to(2, 10);
+    assert!(!motor.at_target());
+    while motor.poll(motor.position() as u64 * 10).unwrap() {}
+    assert!(motor.at_target());
+}
+
+#[test]
+fn step_isr_keeps_errors() {
+    let (mut motor, pins) = motor();
+    motor.step_isr().unwrap();
+    assert!(energized(&pins));
+    motor.set_limits(0, 1);
+    assert_eq!(motor.step_isr().err(), Some(StepError::OutOfLimits));
+    assert_eq!(motor.take_last_error(), Some(StepError::OutOfLimits));
+    assert_eq!(motor.position(), 1);
+}
+
+#[test]
+fn callbacks_capture_state() {
+    let reported = Rc::new(Cell::new(0));
+    let seen = reported.clone();
+    let mut stored = 0;
+    let mut store = |pos: i64| stored = pos;
+    let (motor, _) = motor();
+    let mut motor = motor
+        .with_position_callback(2, move |pos| seen.set(pos))
+        .with_persist_callback(100, &mut store as &mut dyn FnMut(i64));
+    motor.step_for(5, 1).unwrap();
+    assert_eq!(reported.get(), 4);
+    motor.step_for(1, 1).unwrap();
+    assert_eq!(reported.get(), 6);
+    drop(motor);
+    assert_eq!(stored, 6);
+}
+
+/// runs `stepper` every 10 us until it stops, calling `at` with the position after every call
+fn run_accel(stepper: &mut AccelStepper<Pin, Pin, Pin, Pin, Delay>, mut at: impl FnMut(i64)) {
+    let mut now = 0;
+    while stepper.run(now).unwrap() {
+        at(stepper.current_position());
+        now += 10;
+        assert!(now < 100_000_000, "stepper never stopped");
+    }
+}
+
+fn accel_stepper() -> AccelStepper<Pin, Pin, Pin, Pin, Delay> {
+    let mut stepper = AccelStepper::new(motor().0);
+    stepper.set_max_speed(500.0);
+    stepper.set_acceleration(2000.0);
+    stepper
+}
+
+#[test]
+fn accel_stepper_reaches_target() {
+    let mut stepper = accel_stepper();
+    stepper.move_to(300);
+    let mut speeds = std::vec::Vec::new();
+    let mut now = 0;
+    while stepper.run(now).unwrap() {
+        speeds.push(stepper.speed());
+        now += 10;
+    }
+    assert_eq!(stepper.current_position(), 300);
+    assert_eq!(stepper.speed(), 0.0);
+    assert!(!stepper.is_running());
+    let fastest = speeds.iter().copied().fold(0.0, f32::max);
+    assert!((fastest - 500.0).abs() < 1.0);
+    assert!(speeds[0] < 100.0);
+}
+
+#[test]
+fn accel_stepper_reverses_mid_move() {
+    let mut stepper = accel_stepper();
+    stepper.move_to(1000);
+    let mut now = 0;
+    while stepper.current_position() < 200 {
+        stepper.run(now).unwrap();
+        now += 10;
+    }
+    stepper.move_to(-100);
+    let mut furthest = 0;
+    run_accel(&mut stepper, |pos| furthest = furthest.max(pos));
+    assert!(furthest > 200, "decelerates before reversing");
+    assert!(furthest < 1000);
+    assert_eq!(stepper.current_position(), -100);
+    assert!(!stepper.is_running());
+}
+
+#[test]
+fn accel_stepper_full_step_odd_target() {
+    let mut stepper = accel_stepper();
+    stepper.motor().set_drive_mode(DriveMode::FullStep);
+    stepper.move_to(51);
+    run_accel(&mut stepper, |_| ());
+    assert_eq!(stepper.current_position(), 51);
+    stepper.move_to(-3);
+    run_accel(&mut stepper, |_| ());
+    assert_eq!(stepper.current_position(), -3);
+    assert!(!stepper.is_running());
+}
+
+#[test]
+fn linear_move_interleaves_steps() {
+    let track = Rc::new(Cell::new((0, 0)));
+    let (x_track, y_track) = (track.clone(), track.clone());
+    let mut x = motor()
+        .0
+        .with_position_callback(1, move |x| x_track.set((x, x_track.get().1)));
+    let mut y = motor()
+        .0
+        .with_position_callback(1, move |y| y_track.set((y_track.get().0, y)));
+    let mut line = LinearMove::new(&mut x, &mut y, 7, -3, Delay::default());
+    let mut polls = 0;
+    while line.poll(polls, 1).unwrap() {
+        let (x, y) = track.get();
+        // never more than one step off the line from (0, 0) to (7, -3)
+        assert!((x * -3 - y * 7).abs() <= 7, "({x}, {y}) is off the line");
+        polls += 1;
+    }
+    assert_eq!(polls, 6);
+    assert_eq!(track.get(), (7, -3));
+}
+
+#[test]
+fn ramps_are_symmetric() {
+    let (mut motor, _) = motor();
+    for profile in [AccelProfile::Linear, AccelProfile::SCurve] {
+        motor.set_accel_profile(profile);
+        let delays: std::vec::Vec<u32> = motor.compute_ramp_delays(20, 10, 2, 5, 5).collect();
+        assert_eq!(delays.len(), 20);
+        assert_eq!((delays[0], delays[19]), (10, 10));
+        assert!(delays[5..15].iter().all(|&d| d == 2));
+        assert!(delays[..6].windows(2).all(|w| w[0] >= w[1]));
+        for i in 0..20 {
+            assert_eq!(delays[i], delays[19 - i]);
+        }
+    }
+    motor.set_accel_profile(AccelProfile::Linear);
+    let linear: std::vec::Vec<u32> = motor.compute_ramp_delays(20, 10, 2, 5, 5).collect();
+    motor.set_accel_profile(AccelProfile::SCurve);
+    let s_curve: std::vec::Vec<u32> = motor.compute_ramp_delays(20, 10, 2, 5, 5).collect();
+    // the s-curve starts and ends its ramp gentler and is steepest in the middle
+    assert!(s_curve[1] > linear[1]);
+    assert!(s_curve[4] < linear[4]);
+}
+
+#[test]
+fn rotate_fraction_carries_remainder() {
+    let (mut motor, _) = motor();
+    let first = motor.rotate_fraction(1, 12, 1).unwrap();
+    assert_eq!(first.final_position, 341);
+    assert_eq!(motor.fractional_remainder(), (4, 12));
+    for _ in 1..12 {
+        motor.rotate_fraction(1, 12, 1).unwrap();
+    }
+    assert_eq!(motor.position(), 4096);
+    assert_eq!(motor.fractional_remainder(), (0, 12));
+    for _ in 0..12 {
+        motor.rotate_fraction(-1, 12, 1).unwrap();
+    }
+    assert_eq!(motor.position(), 0);
+    assert_eq!(motor.fractional_remainder(), (0, 12));
+    motor.rotate_fraction(1, 12, 1).unwrap();
+    motor.rotate_fraction(1, 5, 1).unwrap();
+    assert_eq!(motor.position(), 341 + 819);
+    assert_eq!(motor.fractional_remainder(), (1, 5));
+}
+
+#[test]
+fn speed_rpm_per_drive_mode() {
+    for (mode, delay) in [
+        (DriveMode::HalfStep, 3),
+        (DriveMode::FullStep, 6),
+        (DriveMode::Wave, 6),
+    ] {
+        let (mut motor, _) = motor();
+        motor.set_drive_mode(mode);
+        motor.set_speed_rpm(5.0).unwrap();
+        assert_eq!(motor.step_delay_ms(), Some(delay));
+    }
+}
+
+#[test]
+fn steps_invalidate_undo() {
+    let (mut motor, _) = motor();
+    motor.step_for(4, 1).unwrap();
+    assert_eq!(motor.undo_last_move(1).unwrap().unwrap().final_position, 0);
+    assert_eq!(motor.undo_last_move(1).unwrap().unwrap().final_position, 4);
+
+    motor.step_uncounted(Direction::Normal).unwrap();
+    assert!(motor.undo_last_move(1).unwrap().is_none());
+
+    motor.step_for(2, 1).unwrap();
+    motor.step_isr().unwrap();
+    motor.step_isr().unwrap();
+    motor.set_direction(Direction::Reverse);
+    StepperMotor::step(&mut motor).unwrap();
+    StepperMotor::step(&mut motor).unwrap();
+    assert_eq!(motor.position(), 6);
+    assert!(motor.undo_last_move(1).unwrap().is_none());
+
+    motor.step_for(1, 1).unwrap();
+    motor.with_initial_position(5);
+    assert!(motor.undo_last_move(1).unwrap().is_none());
+}
+
+#[test]
+fn backlash_survives_count_inversion() {
+    let delay = Delay::default();
+    let [a, b, c, d]: [Pin; 4] = Default::default();
+    let mut motor: Motor = ULN2003::new(a, b, c, d, Some(delay.clone()));
+    motor.set_backlash_steps(3);
+    motor.step_for(2, 1).unwrap();
+    motor.set_count_inverted(true);
+    let before = delay.0.get();
+    // still turning the same way physically, so there is no slack to take up
+    motor.step_segments(&[(Direction::Normal, 1)], 1).unwrap();
+    assert_eq!((delay.0.get() - before) / 1_000_000, 1);
+}
+
+#[test]
+fn reset_config_resets_count_inversion() {
+    let (mut motor, _) = motor();
+    motor
+        .set_count_inverted(true)
+        .set_direction(Direction::Reverse)
+        .set_drive_mode(DriveMode::Wave)
+        .set_limits(-10, 10);
+    motor.step_for(2, 1).unwrap();
+    let pos = motor.position();
+    assert!(pos > 0);
+    motor.reset_config();
+    assert_eq!(motor.position(), pos);
+    assert_eq!(motor.drive_mode(), DriveMode::HalfStep);
+    assert_eq!(motor.limits(), None);
+    motor.step_for(1, 1).unwrap();
+    assert_eq!(motor.position(), pos + 1);
+}